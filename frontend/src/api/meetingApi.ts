@@ -1,4 +1,4 @@
-import { Meeting, MeetingPosition } from "../models";
+import { Meeting, MeetingPosition, Organization } from "../models";
 
 function pad(a: any, b: number) {
     return ([1e15] + a).slice(-b)
@@ -9,75 +9,171 @@ export interface MeetingFetchDistanceOptions {
     distance: number
 }
 
+export interface MeetingBounds {
+    west: number,
+    south: number,
+    east: number,
+    north: number
+}
+
+/// The filters of the `/meetings` endpoint, so the server only returns the meetings that are shown.
 export interface MeetingFetchOptions {
-    distance?: MeetingFetchDistanceOptions
+    distance?: MeetingFetchDistanceOptions,
+    bounds?: MeetingBounds,
+    text?: string,
+    types?: string[],
+    attendance?: string[],
+    orgs?: Organization[],
+    days?: string[],
+    country?: string,
+    region?: string,
+    limit?: number,
+    offset?: number
+}
+
+export interface MeetingPage {
+    /// The number of meetings matching the filters, across all pages.
+    total: number,
+    meetings: Meeting[]
 }
 
-export async function fetchMeetings(opts?: MeetingFetchOptions): Promise<Meeting[]> {
-    let url = import.meta.env.VITE_API_URL + '/meetings';
+const defaultLimit = 1000;
+
+function searchParams(opts: MeetingFetchOptions): URLSearchParams {
+    const params = new URLSearchParams();
+
+    if (opts.distance) {
+        params.set('longitude', `${opts.distance.position.longitude}`);
+        params.set('latitude', `${opts.distance.position.latitude}`);
+        params.set('distance', `${opts.distance.distance}`);
+    }
+
+    if (opts.bounds) {
+        const { west, south, east, north } = opts.bounds;
+        params.set('bbox', [west, south, east, north].join(','));
+    }
 
-    if (opts) {
-        url += '?';
+    if (opts.text) {
+        params.set('q', opts.text);
+    }
+
+    const lists: [string, string[] | undefined][] = [
+        ['types', opts.types],
+        ['attendance', opts.attendance],
+        ['orgs', opts.orgs],
+        ['days', opts.days],
+    ];
 
-        if (opts.distance) {
-            url += `longitude=${encodeURIComponent(opts.distance.position.longitude)}&latitude=${encodeURIComponent(opts.distance.position.latitude)}&distance=${encodeURIComponent(opts.distance.distance)}`;
+    for (const [name, values] of lists) {
+        if (values && values.length > 0) {
+            params.set(name, values.join(','));
         }
     }
 
-    const request = await fetch(url);
-    const response = await request.json() as ApiSearchMeeting[];
+    if (opts.country) {
+        params.set('country', opts.country);
+    }
+
+    if (opts.region) {
+        params.set('region', opts.region);
+    }
+
+    params.set('limit', `${opts.limit ?? defaultLimit}`);
+    params.set('offset', `${opts.offset ?? 0}`);
 
-    let id = 0;
+    return params;
+}
 
-    return response.map(searchMeeting => {
-        let apiMeeting = searchMeeting.meeting;
+/// Fetch a single page of the meetings matching `opts`, the first `defaultLimit` unless a limit is given.
+export async function fetchMeetings(opts: MeetingFetchOptions = {}): Promise<MeetingPage> {
+    const request = await fetch(`${import.meta.env.VITE_API_URL}/meetings?${searchParams(opts)}`);
+    const page = await request.json() as ApiSearchResults;
 
-        let isRecurring = !!apiMeeting.time.recurring;
-        let formattedTime = '';
+    return {
+        total: page.total,
+        meetings: page.meetings.map(toMeeting),
+    };
+}
 
-        if (isRecurring) {
-            let hours = apiMeeting.time.recurring.hour;
-            let minutes = apiMeeting.time.recurring.minute;
+let id = 0;
 
-            formattedTime = `Every ${apiMeeting.time.recurring.day} at ${hours}:${minutes}`;
+function toMeeting(searchMeeting: ApiSearchMeeting): Meeting {
+    let apiMeeting = searchMeeting.meeting;
 
-            if (apiMeeting.duration) {
-                let seconds = apiMeeting.duration.secs;
+    let [formattedTime, day] = formatTime(apiMeeting.time);
+    let isRecurring = !apiMeeting.time.once;
 
-                let endHours = Math.floor(seconds / 3600);
-                seconds %= endHours;
+    if (apiMeeting.duration) {
+        let time = timeOf(apiMeeting.time);
+        let seconds = apiMeeting.duration.secs;
 
-                let endMinutes = Math.floor(seconds / 60);
+        let endHours = Math.floor(seconds / 3600);
+        seconds %= endHours;
 
-                formattedTime += ` - ${pad(endHours + (+hours), 2)}:${pad(endMinutes + (+minutes), 2)}`;
-            }
-        }
+        let endMinutes = Math.floor(seconds / 60);
 
-        return {
-            name: apiMeeting.name,
-            id: ++id,
-            notes: apiMeeting.notes,
-            day: apiMeeting.time.recurring.day,
-            country: apiMeeting.location.country,
-            position: apiMeeting.location.position,
-            online: apiMeeting.online_options.is_online,
-            org: apiMeeting.org,
-            address: apiMeeting.location.address,
-            region: apiMeeting.location.region,
-            onlineUrl: apiMeeting.online_options.url,
-            distance: searchMeeting.distance,
-            source: apiMeeting.source,
-            durationInSecs: apiMeeting.duration?.secs,
-            locationName: apiMeeting.location.name,
-            onlineNotes: apiMeeting.online_options.notes,
-            locationNotes: apiMeeting.location.notes,
-            email: apiMeeting.contact.email,
-            phone: apiMeeting.contact.phone,
-            recurring: isRecurring,
-            formattedTime,
-            updatedAt: new Date(apiMeeting.updated_at)
-        };
-    })
+        formattedTime += ` - ${pad(endHours + (+time.hour), 2)}:${pad(endMinutes + (+time.minute), 2)}`;
+    }
+
+    return {
+        name: apiMeeting.name,
+        id: ++id,
+        notes: apiMeeting.notes,
+        day,
+        country: apiMeeting.location.country,
+        position: apiMeeting.location.position,
+        online: apiMeeting.attendance == ApiAttendanceMode.Online || apiMeeting.attendance == ApiAttendanceMode.Hybrid,
+        org: apiMeeting.org,
+        address: apiMeeting.location.address,
+        region: apiMeeting.location.region,
+        onlineUrl: apiMeeting.online_options.url,
+        distance: searchMeeting.distance,
+        source: apiMeeting.source,
+        durationInSecs: apiMeeting.duration?.secs,
+        locationName: apiMeeting.location.name,
+        onlineNotes: apiMeeting.online_options.notes,
+        locationNotes: apiMeeting.location.notes,
+        email: apiMeeting.contact.email,
+        phone: apiMeeting.contact.phone,
+        recurring: isRecurring,
+        formattedTime,
+        updatedAt: new Date(apiMeeting.updated_at)
+    };
+}
+
+const weekDays = ['Sunday', 'Monday', 'Tuesday', 'Wednesday', 'Thursday', 'Friday', 'Saturday'];
+
+function timeOf(time: ApiTime): ApiTimeOfDay {
+    return time.recurring ?? time.monthly ?? time.biweekly ?? time.once!;
+}
+
+function formatTime(time: ApiTime): [string, string] {
+    let { hour, minute } = timeOf(time);
+    let at = `at ${hour}:${minute}`;
+
+    if (time.monthly) {
+        let week = time.monthly.week == -1 ? 'last' : `${time.monthly.week}${['st', 'nd', 'rd'][time.monthly.week - 1] ?? 'th'}`;
+        return [`Every ${week} ${time.monthly.day} of the month ${at}`, time.monthly.day];
+    }
+
+    if (time.biweekly) {
+        let day = weekDays[new Date(time.biweekly.anchor).getUTCDay()];
+        return [`Every other ${day} ${at}`, day];
+    }
+
+    if (time.once) {
+        let day = weekDays[new Date(time.once.date).getUTCDay()];
+        return [`On ${time.once.date} ${at}`, day];
+    }
+
+    return [`Every ${time.recurring!.day} ${at}`, time.recurring!.day];
+}
+
+interface ApiSearchResults {
+    total: number;
+    offset: number;
+    limit: number | null;
+    meetings: ApiSearchMeeting[];
 }
 
 interface ApiSearchMeeting {
@@ -92,6 +188,7 @@ interface ApiMeeting {
     source: string;
     contact: ApiContact;
     location: ApiLocation;
+    attendance: ApiAttendanceMode;
     online_options: ApiOnlineOptions;
     time: ApiTime;
     duration: ApiDuration;
@@ -125,7 +222,13 @@ interface ApiPosition {
 interface ApiOnlineOptions {
     url: null | string;
     notes: null | string;
-    is_online: boolean;
+}
+
+enum ApiAttendanceMode {
+    InPerson = "InPerson",
+    Online = "Online",
+    Hybrid = "Hybrid",
+    TemporarilyInactive = "TemporarilyInactive",
 }
 
 enum ApiOrg {
@@ -137,15 +240,34 @@ enum ApiOrg {
 }
 
 interface ApiTime {
-    recurring: ApiRecurring;
+    recurring?: ApiRecurring;
+    monthly?: ApiMonthly;
+    biweekly?: ApiBiweekly;
+    once?: ApiOnce;
 }
 
-interface ApiRecurring {
-    day: ApiDay;
+interface ApiTimeOfDay {
     hour: number;
     minute: number;
 }
 
+interface ApiRecurring extends ApiTimeOfDay {
+    day: ApiDay;
+}
+
+interface ApiMonthly extends ApiTimeOfDay {
+    week: number;
+    day: ApiDay;
+}
+
+interface ApiBiweekly extends ApiTimeOfDay {
+    anchor: string;
+}
+
+interface ApiOnce extends ApiTimeOfDay {
+    date: string;
+}
+
 enum ApiDay {
     Friday = "Friday",
     Monday = "Monday",
@@ -1,7 +1,7 @@
 import './style/main.scss'
 
 import { initialize } from "./view";
-import { fetchMeetings, fetchPositionByQuery } from "./api";
+import { fetchMeetings, fetchPositionByQuery, MeetingBounds, MeetingFetchOptions } from "./api";
 
 
 document.body.onload = async () => {
@@ -14,9 +14,35 @@ document.body.onload = async () => {
     results.setShowInfoCallback(m => popup.showMeeting(m));
     results.setViewOnMapCallback(m => map.focus(m));
 
+    let filters: MeetingFetchOptions = {};
+    let lastRequest = 0;
+
+    // Only the meetings in view are fetched, again whenever the map moves or the search changes.
+    const showMeetings = async (bounds: MeetingBounds) => {
+        const request = ++lastRequest;
+
+        map.clear();
+        results.clear();
+
+        results.setLoading(true);
+
+        const page = await fetchMeetings({ ...filters, bounds });
+
+        // A newer request replaced this one while it was loading
+        if (request != lastRequest) {
+            return;
+        }
+
+        results.setLoading(false);
+
+        results.addMeetings(page.meetings);
+        map.addMeetings(page.meetings);
+    };
+
+    map.setBoundsChangeCallback(showMeetings);
+
     searchBar.setOnSearchCallback(async query => {
         let position = null;
-        let distanceOpt = undefined;
 
         if (query.location) {
             position = await fetchPositionByQuery(query.location);
@@ -27,29 +53,17 @@ document.body.onload = async () => {
             }
         }
 
-        map.clear();
-        results.clear();
-
-        results.setLoading(true);
-
-        let meetings = await fetchMeetings({
+        filters = {
             distance: position ? { position, distance: query.distance } : undefined,
-        })
-
-        results.setLoading(false);
-
-        results.addMeetings(meetings);
-        map.addMeetings(meetings);
+        };
 
         if (position) {
+            // Moving the map shows the meetings around the position
             map.goToPosition(position);
+        } else {
+            await showMeetings(map.getBounds());
         }
     })
 
-    let meetings = await fetchMeetings();
-
-    results.setLoading(false);
-    results.addMeetings(meetings);
-
-    map.addMeetings(meetings);
+    await showMeetings(map.getBounds());
 };
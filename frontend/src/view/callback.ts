@@ -1,3 +1,5 @@
 import { Meeting } from "../models";
+import { MeetingBounds } from "../api";
 
 export type MeetingCallback = ((meeting: Meeting) => void) | null;
+export type BoundsCallback = ((bounds: MeetingBounds) => void) | null;
@@ -13,8 +13,9 @@ import {
 } from "leaflet";
 import 'leaflet.markercluster';
 import {Meeting, MeetingPosition, Organization} from "../models";
+import {MeetingBounds} from "../api";
 import {getLogoImgUrlByOrg} from "./logo";
-import {MeetingCallback} from "./callback";
+import {BoundsCallback, MeetingCallback} from "./callback";
 
 interface MapMeetingActions {
     remove: () => void,
@@ -33,6 +34,9 @@ class MeetingMarker extends Marker {
 
 const MAX_ZOOM = 16;
 
+/// How long the map has to stand still before the meetings in view are fetched.
+const MOVE_DEBOUNCE_MS = 300;
+
 export class MeetingMap {
     private readonly map: Map;
     private readonly cluster: MarkerClusterGroup;
@@ -40,6 +44,8 @@ export class MeetingMap {
     private iconCache: { [key: string]: Icon } = {};
     private actions: { [id: number]: MapMeetingActions } = {};
     private clickCallback: MeetingCallback = null;
+    private boundsCallback: BoundsCallback = null;
+    private moveTimeout: any;
 
 
     public constructor(id: string) {
@@ -84,6 +90,23 @@ export class MeetingMap {
             center: {lng: 0, lat: 0},
             layers: [mapLayer, this.cluster],
         });
+
+        this.map.on('moveend', () => {
+            clearTimeout(this.moveTimeout);
+            this.moveTimeout = setTimeout(() => this.boundsCallback?.(this.getBounds()), MOVE_DEBOUNCE_MS);
+        });
+    }
+
+    /// The part of the world in view, the map can show more than one world when zoomed out.
+    public getBounds(): MeetingBounds {
+        const bounds = this.map.getBounds();
+
+        return {
+            west: Math.max(bounds.getWest(), -180),
+            south: Math.max(bounds.getSouth(), -90),
+            east: Math.min(bounds.getEast(), 180),
+            north: Math.min(bounds.getNorth(), 90),
+        };
     }
 
     private sampleMarkers(markers: MeetingMarker[], maxMarkers: number): MeetingMarker[] {
@@ -201,4 +224,8 @@ export class MeetingMap {
     public setMeetingClickCallback(callback: MeetingCallback) {
         this.clickCallback = callback;
     }
+
+    public setBoundsChangeCallback(callback: BoundsCallback) {
+        this.boundsCallback = callback;
+    }
 }
@@ -0,0 +1,213 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono_tz::Tz;
+
+use crate::geocoder::GeocodeRules;
+use serde::Deserialize;
+use thiserror::Error;
+
+/// Used when the data directory has no config.toml.
+const DEFAULT_CONFIG: &str = include_str!("../default-config.toml");
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("TOML parse error: {0}")]
+    ParseError(#[from] toml::de::Error),
+
+    #[error("Invalid source {index}: {reason}")]
+    InvalidSource { index: usize, reason: String },
+
+    #[error("Invalid geocoder: {0}")]
+    InvalidGeocoder(String),
+
+    #[error("No sources configured, a sync would remove every meeting")]
+    NoSources,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SourceType {
+    Tsml,
+    Bmlt,
+    NaHolland,
+}
+
+impl fmt::Display for SourceType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SourceType::Tsml => "tsml",
+            SourceType::Bmlt => "bmlt",
+            SourceType::NaHolland => "na-holland",
+        };
+
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SourceConfig {
+    #[serde(rename = "type")]
+    pub source_type: SourceType,
+    /// Identifies the source, instead of the name derived from its type or url.
+    pub name: Option<String>,
+    pub url: Option<String>,
+    pub organization: Option<String>,
+    /// The ISO 3166-1 alpha-2 code of the country of meetings that do not have one.
+    pub country: Option<String>,
+    /// The time zone of meetings that do not have one.
+    pub timezone: Option<Tz>,
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GeocoderProvider {
+    Nominatim,
+    Photon,
+    Positionstack,
+    Http,
+    /// Only the gazetteer loaded with `gazetteer load`, without any network requests.
+    Offline,
+}
+
+impl GeocoderProvider {
+    /// The time between two requests when the configuration does not set a `rate_limit`.
+    pub fn default_rate_limit(&self) -> Duration {
+        match self {
+            // The usage policy of the public instance allows one request per second
+            GeocoderProvider::Nominatim => Duration::from_secs(1),
+            GeocoderProvider::Photon | GeocoderProvider::Positionstack | GeocoderProvider::Http => {
+                Duration::from_secs(2)
+            }
+            GeocoderProvider::Offline => Duration::ZERO,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GeocoderConfig {
+    pub provider: GeocoderProvider,
+    /// Overrides the public instance of the provider.
+    pub base_url: Option<String>,
+    pub api_key: Option<String>,
+    /// Try the gazetteer loaded with `gazetteer load` before the provider.
+    #[serde(default)]
+    pub offline: bool,
+    /// The minimum number of seconds between two requests to the provider.
+    pub rate_limit: Option<f64>,
+
+    // Only used by the http provider
+    /// The URL parameter of the api_key, `key` by default.
+    pub api_key_param: Option<String>,
+    /// The URL parameter of the query, `q` by default.
+    pub query_param: Option<String>,
+    /// Sent along with every query.
+    #[serde(default)]
+    pub params: BTreeMap<String, String>,
+    /// JSON pointer to the latitude in the response.
+    pub latitude: Option<String>,
+    /// JSON pointer to the longitude in the response.
+    pub longitude: Option<String>,
+    /// JSON pointer to the precision in the response, one of rooftop, street, city, region
+    /// or country.
+    pub precision: Option<String>,
+    /// JSON pointer to the confidence in the response, from 0 to 1.
+    pub confidence: Option<String>,
+    /// JSON pointer to the ISO 3166-1 alpha-2 code of the country in the response.
+    pub country_code: Option<String>,
+
+    #[serde(default)]
+    pub rules: GeocodeRules,
+}
+
+/// A GeoJSON dataset of boundaries used to set the country and region of meetings.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BoundariesConfig {
+    /// Relative to the data directory.
+    pub path: PathBuf,
+    /// The property with the ISO 3166-1 alpha-2 code of the country.
+    #[serde(default = "default_country_property")]
+    pub country_property: String,
+    /// The property with the name of the region.
+    #[serde(default = "default_region_property")]
+    pub region_property: String,
+}
+
+fn default_country_property() -> String {
+    String::from("iso_a2")
+}
+
+fn default_region_property() -> String {
+    String::from("name")
+}
+
+/// A GeoJSON dataset of time zone boundaries used to set the time zone of meetings that do not
+/// have one.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TimeZonesConfig {
+    /// Relative to the data directory.
+    pub path: PathBuf,
+    /// The property with the IANA name of the time zone.
+    #[serde(default = "default_timezone_property")]
+    pub property: String,
+}
+
+fn default_timezone_property() -> String {
+    String::from("tzid")
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    #[serde(default = "default_sources")]
+    pub sources: Vec<SourceConfig>,
+    #[serde(default = "default_geocoder")]
+    pub geocoder: GeocoderConfig,
+    pub boundaries: Option<BoundariesConfig>,
+    pub timezones: Option<TimeZonesConfig>,
+}
+
+/// Config files that only change the geocoder keep the built-in sources.
+fn default_sources() -> Vec<SourceConfig> {
+    Config::default().sources
+}
+
+/// Config files from before the geocoder was configurable get the default.
+fn default_geocoder() -> GeocoderConfig {
+    Config::default().geocoder
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        toml::from_str(DEFAULT_CONFIG).unwrap()
+    }
+}
+
+impl Config {
+    /// Load config.toml from the data directory, or the built-in defaults when it does not exist.
+    pub fn load(data_path: &Path) -> Result<Self, ConfigError> {
+        let path = data_path.join("config.toml");
+
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let config: Self = toml::from_str(&std::fs::read_to_string(path)?)?;
+
+        if config.sources.is_empty() {
+            return Err(ConfigError::NoSources);
+        }
+
+        Ok(config)
+    }
+}
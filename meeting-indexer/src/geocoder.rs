@@ -0,0 +1,256 @@
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::config::{GeocoderConfig, GeocoderProvider};
+use crate::meeting::Position;
+use futures_util::future::BoxFuture;
+use serde::Deserialize;
+use thiserror::Error;
+
+use self::offline::OfflineGeocoder;
+
+pub mod boundaries;
+mod http;
+mod nominatim;
+pub mod offline;
+mod photon;
+mod positionstack;
+
+/// Public geocoding services ask to be identified.
+const USER_AGENT: &str = concat!("meeting-indexer/", env!("CARGO_PKG_VERSION"));
+
+#[derive(Debug, Error)]
+pub enum GeocodeError {
+    #[error("HTTP Request error: {0}")]
+    HttpRequestError(#[from] reqwest::Error),
+
+    #[error("JSON parse error: {0}")]
+    JsonParseError(#[from] serde_json::Error),
+
+    #[error("Unexpected response: {0}")]
+    UnexpectedResponse(String),
+
+    #[error("SQL error: {0}")]
+    SqliteError(#[from] rusqlite::Error),
+}
+
+/// How exactly a result points at the place that was searched, from fine to coarse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Precision {
+    Rooftop,
+    Street,
+    City,
+    Region,
+    Country,
+}
+
+impl fmt::Display for Precision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Precision::Rooftop => "rooftop",
+            Precision::Street => "street",
+            Precision::City => "city",
+            Precision::Region => "region",
+            Precision::Country => "country",
+        };
+
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum PrecisionParseError {
+    UnknownPrecision,
+}
+
+impl FromStr for Precision {
+    type Err = PrecisionParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rooftop" => Ok(Self::Rooftop),
+            "street" => Ok(Self::Street),
+            "city" => Ok(Self::City),
+            "region" => Ok(Self::Region),
+            "country" => Ok(Self::Country),
+            _ => Err(PrecisionParseError::UnknownPrecision),
+        }
+    }
+}
+
+/// A position found by a geocoder, along with how much it can be trusted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Geocode {
+    pub position: Position,
+    pub precision: Option<Precision>,
+    /// From 0 to 1, for the geocoders that report it.
+    pub confidence: Option<f64>,
+    /// ISO 3166-1 alpha-2 code of the country the position is in.
+    pub country_code: Option<String>,
+    /// The name of the country, in the language of the geocoder.
+    pub country: Option<String>,
+}
+
+impl Geocode {
+    pub fn new(position: Position, precision: Option<Precision>) -> Self {
+        Self {
+            position,
+            precision,
+            confidence: None,
+            country_code: None,
+            country: None,
+        }
+    }
+
+    /// Set the country, its code is only kept when it is an alpha-2 code.
+    pub fn with_country(mut self, code: Option<&str>, name: Option<&str>) -> Self {
+        self.country_code = code
+            .filter(|code| code.len() == 2)
+            .map(|code| code.to_uppercase());
+        self.country = name.map(str::to_string);
+        self
+    }
+
+    /// Whether the result is in the country with the given ISO 3166-1 alpha-2 code. Unknown
+    /// when the result has no country code, or `country` is not a code, like the country names
+    /// some sources give their meetings.
+    fn in_country(&self, country: &str) -> Option<bool> {
+        let country = country.trim();
+
+        if country.len() != 2 || !country.chars().all(|c| c.is_ascii_alphabetic()) {
+            return None;
+        }
+
+        self.country_code
+            .as_deref()
+            .map(|found| found.eq_ignore_ascii_case(country))
+    }
+}
+
+pub type GeocodeResult = Result<Option<Geocode>, GeocodeError>;
+
+/// Which results of geocoders are rather left out than shown as a misleading position.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct GeocodeRules {
+    /// Reject results coarser than this, like `city` to reject regions and countries.
+    pub min_precision: Option<Precision>,
+    /// Reject results with a lower confidence.
+    pub min_confidence: Option<f64>,
+    /// Reject results outside the country the source declares for the meeting.
+    #[serde(default)]
+    pub same_country: bool,
+}
+
+impl GeocodeRules {
+    /// Why the result should not be used, if it should not.
+    pub fn check(&self, geocode: &Geocode, declared_country: Option<&str>) -> Option<String> {
+        if let (Some(min_precision), Some(precision)) = (self.min_precision, geocode.precision) {
+            if precision > min_precision {
+                return Some(format!(
+                    "{precision} precision is coarser than {min_precision}"
+                ));
+            }
+        }
+
+        if let (Some(min_confidence), Some(confidence)) = (self.min_confidence, geocode.confidence)
+        {
+            if confidence < min_confidence {
+                return Some(format!(
+                    "confidence {confidence} is lower than {min_confidence}"
+                ));
+            }
+        }
+
+        if self.same_country {
+            if let Some(declared_country) = declared_country {
+                if geocode.in_country(declared_country) == Some(false) {
+                    let found = geocode.country_code.as_deref().unwrap_or_default();
+
+                    return Some(format!("found in {found} instead of {declared_country}"));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// A service that turns an address into a position.
+pub trait Geocoder: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// The best match for the query, if anything matched.
+    fn search<'a>(&'a self, query: &'a str) -> BoxFuture<'a, GeocodeResult>;
+}
+
+/// The geocoders of the configuration, the offline one is asked first.
+pub struct Geocoders {
+    pub offline: Option<OfflineGeocoder>,
+    pub network: Option<Box<dyn Geocoder>>,
+    /// The minimum time between two requests to the network geocoder.
+    pub rate_limit: Duration,
+}
+
+/// The gazetteer of the offline geocoder, in the data directory.
+pub fn gazetteer_path(data_path: &Path) -> std::path::PathBuf {
+    data_path.join("gazetteer.db")
+}
+
+pub fn from_config(config: &GeocoderConfig, data_path: &Path) -> Result<Geocoders, String> {
+    let offline = if config.offline || config.provider == GeocoderProvider::Offline {
+        let geocoder = OfflineGeocoder::open(&gazetteer_path(data_path))
+            .map_err(|e| format!("could not open the gazetteer: {e}"))?;
+        Some(geocoder)
+    } else {
+        None
+    };
+
+    let rate_limit = match config.rate_limit {
+        Some(seconds) => Duration::try_from_secs_f64(seconds)
+            .map_err(|_| format!("invalid rate_limit {seconds}, expected a number of seconds"))?,
+        None => config.provider.default_rate_limit(),
+    };
+
+    Ok(Geocoders {
+        offline,
+        network: network_from_config(config)?,
+        rate_limit,
+    })
+}
+
+fn network_from_config(config: &GeocoderConfig) -> Result<Option<Box<dyn Geocoder>>, String> {
+    let client = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let geocoder: Box<dyn Geocoder> = match config.provider {
+        GeocoderProvider::Nominatim => Box::new(nominatim::Nominatim::new(
+            client,
+            config.base_url.as_deref(),
+            config.api_key.as_deref(),
+        )),
+        GeocoderProvider::Photon => {
+            Box::new(photon::Photon::new(client, config.base_url.as_deref()))
+        }
+        GeocoderProvider::Positionstack => {
+            let api_key = config
+                .api_key
+                .as_deref()
+                .ok_or("positionstack needs an api_key")?;
+
+            Box::new(positionstack::Positionstack::new(
+                client,
+                config.base_url.as_deref(),
+                api_key,
+            ))
+        }
+        GeocoderProvider::Http => Box::new(http::HttpGeocoder::from_config(client, config)?),
+        GeocoderProvider::Offline => return Ok(None),
+    };
+
+    Ok(Some(geocoder))
+}
@@ -0,0 +1,499 @@
+use std::path::Path;
+
+use crate::config::{BoundariesConfig, TimeZonesConfig};
+use crate::meeting::{parse_timezone, Location, Position};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum BoundariesError {
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("JSON parse error: {0}")]
+    JsonParseError(#[from] serde_json::Error),
+}
+
+/// The country and first-level region a position is in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Area {
+    /// ISO 3166-1 alpha-2 code, like `NL`.
+    pub country: Option<String>,
+    pub region: Option<String>,
+}
+
+/// A ring of longitude, latitude pairs.
+type Ring = Vec<(f64, f64)>;
+
+struct Boundary<T> {
+    value: T,
+    /// The outer ring of every polygon followed by its holes.
+    polygons: Vec<Vec<Ring>>,
+    /// Min longitude, min latitude, max longitude, max latitude.
+    bbox: (f64, f64, f64, f64),
+}
+
+impl<T> Boundary<T> {
+    fn contains(&self, x: f64, y: f64) -> bool {
+        let (min_x, min_y, max_x, max_y) = self.bbox;
+
+        if x < min_x || x > max_x || y < min_y || y > max_y {
+            return false;
+        }
+
+        self.polygons.iter().any(|rings| {
+            let mut rings = rings.iter();
+
+            rings.next().is_some_and(|outer| ring_contains(outer, x, y))
+                && !rings.any(|hole| ring_contains(hole, x, y))
+        })
+    }
+}
+
+/// Even-odd rule: a point is inside when a ray from it crosses the ring an odd number of times.
+fn ring_contains(ring: &Ring, x: f64, y: f64) -> bool {
+    let mut inside = false;
+
+    for (i, &(xi, yi)) in ring.iter().enumerate() {
+        let (xj, yj) = ring[(i + ring.len() - 1) % ring.len()];
+
+        if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+    }
+
+    inside
+}
+
+/// The width and height in degrees of a cell of the grid.
+const CELL_SIZE: f64 = 1.0;
+const COLUMNS: usize = (360.0 / CELL_SIZE) as usize;
+const ROWS: usize = (180.0 / CELL_SIZE) as usize;
+
+/// The boundaries whose bounding box overlaps each cell of a grid over the world, so a position
+/// is only tested against the boundaries near it.
+struct Grid {
+    cells: Vec<Vec<usize>>,
+}
+
+impl Grid {
+    fn new<T>(boundaries: &[Boundary<T>]) -> Self {
+        let mut cells = vec![Vec::new(); COLUMNS * ROWS];
+
+        for (i, boundary) in boundaries.iter().enumerate() {
+            let (min_x, min_y, max_x, max_y) = boundary.bbox;
+
+            if min_x > max_x || min_y > max_y {
+                // A boundary without points
+                continue;
+            }
+
+            for row in Self::row(min_y)..=Self::row(max_y) {
+                for column in Self::column(min_x)..=Self::column(max_x) {
+                    cells[row * COLUMNS + column].push(i);
+                }
+            }
+        }
+
+        Self { cells }
+    }
+
+    fn column(x: f64) -> usize {
+        (((x + 180.0) / CELL_SIZE).floor().max(0.0) as usize).min(COLUMNS - 1)
+    }
+
+    fn row(y: f64) -> usize {
+        (((y + 90.0) / CELL_SIZE).floor().max(0.0) as usize).min(ROWS - 1)
+    }
+
+    /// The indexes of the boundaries that might contain the point, in the order they were loaded.
+    fn candidates(&self, x: f64, y: f64) -> &[usize] {
+        &self.cells[Self::row(y) * COLUMNS + Self::column(x)]
+    }
+}
+
+/// The features of a GeoJSON dataset with the value each of them stands for.
+struct Boundaries<T> {
+    boundaries: Vec<Boundary<T>>,
+    grid: Grid,
+}
+
+impl<T> Boundaries<T> {
+    /// Parse the features for which `value` finds a value in their properties.
+    fn parse(
+        text: &str,
+        value: impl Fn(&dyn Fn(&str) -> Option<String>) -> Option<T>,
+    ) -> Result<Self, BoundariesError> {
+        let collection: FeatureCollection = serde_json::from_str(text)?;
+
+        let boundaries = collection
+            .features
+            .into_iter()
+            .filter_map(|feature| {
+                let polygons = match feature.geometry? {
+                    Geometry::Polygon { coordinates } => vec![to_rings(coordinates)],
+                    Geometry::MultiPolygon { coordinates } => {
+                        coordinates.into_iter().map(to_rings).collect()
+                    }
+                    Geometry::Other => return None,
+                };
+
+                let property = |name: &str| {
+                    feature
+                        .properties
+                        .as_ref()
+                        .and_then(|properties| properties.get(name))
+                        .and_then(Value::as_str)
+                        .map(str::to_string)
+                };
+
+                Some(Boundary {
+                    value: value(&property)?,
+                    bbox: bbox(&polygons),
+                    polygons,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        Ok(Self {
+            grid: Grid::new(&boundaries),
+            boundaries,
+        })
+    }
+
+    fn locate(&self, position: &Position) -> Option<&T> {
+        // Areas split at the antimeridian start at -180 on its east side
+        let x = if position.longitude >= 180.0 {
+            position.longitude - 360.0
+        } else {
+            position.longitude
+        };
+
+        self.grid
+            .candidates(x, position.latitude)
+            .iter()
+            .map(|&i| &self.boundaries[i])
+            .find(|boundary| boundary.contains(x, position.latitude))
+            .map(|boundary| &boundary.value)
+    }
+}
+
+/// Finds the country and region of positions in a GeoJSON dataset of boundaries, like the
+/// admin 1 states and provinces of Natural Earth.
+pub struct ReverseGeocoder {
+    areas: Boundaries<Area>,
+}
+
+impl ReverseGeocoder {
+    pub fn load(data_path: &Path, config: &BoundariesConfig) -> Result<Self, BoundariesError> {
+        let text = std::fs::read_to_string(data_path.join(&config.path))?;
+        Self::parse(&text, config)
+    }
+
+    fn parse(text: &str, config: &BoundariesConfig) -> Result<Self, BoundariesError> {
+        let areas = Boundaries::parse(text, |property| {
+            // Natural Earth uses codes like -99 for disputed areas
+            let country = property(&config.country_property)
+                .filter(|code| code.len() == 2 && code.chars().all(|c| c.is_ascii_alphabetic()))
+                .map(|code| code.to_uppercase());
+
+            Some(Area {
+                country,
+                region: property(&config.region_property).filter(|name| !name.is_empty()),
+            })
+        })?;
+
+        Ok(Self { areas })
+    }
+
+    pub fn locate(&self, position: &Position) -> Option<&Area> {
+        self.areas.locate(position)
+    }
+
+    /// Set the country and region of a location from its position. Returns whether anything
+    /// changed.
+    pub fn correct(&self, location: &mut Location) -> bool {
+        let Some(area) = location.position.as_ref().and_then(|p| self.locate(p)) else {
+            return false;
+        };
+
+        let mut changed = false;
+
+        if area.country.is_some() && location.country != area.country {
+            location.country = area.country.clone();
+            changed = true;
+        }
+
+        if area.region.is_some() && location.region != area.region {
+            location.region = area.region.clone();
+            changed = true;
+        }
+
+        changed
+    }
+}
+
+/// Finds the time zone of positions in a GeoJSON dataset of time zone boundaries, like the one
+/// of timezone-boundary-builder.
+pub struct TimeZoneLocator {
+    zones: Boundaries<Tz>,
+}
+
+impl TimeZoneLocator {
+    pub fn load(data_path: &Path, config: &TimeZonesConfig) -> Result<Self, BoundariesError> {
+        let text = std::fs::read_to_string(data_path.join(&config.path))?;
+        Self::parse(&text, config)
+    }
+
+    fn parse(text: &str, config: &TimeZonesConfig) -> Result<Self, BoundariesError> {
+        let zones = Boundaries::parse(text, |property| {
+            property(&config.property).and_then(|name| parse_timezone(&name))
+        })?;
+
+        Ok(Self { zones })
+    }
+
+    pub fn locate(&self, position: &Position) -> Option<Tz> {
+        self.zones.locate(position).copied()
+    }
+}
+
+fn to_rings(coordinates: Vec<Vec<Vec<f64>>>) -> Vec<Ring> {
+    coordinates
+        .into_iter()
+        .map(|ring| {
+            ring.into_iter()
+                .filter(|point| point.len() >= 2)
+                .map(|point| (point[0], point[1]))
+                .collect()
+        })
+        .collect()
+}
+
+fn bbox(polygons: &[Vec<Ring>]) -> (f64, f64, f64, f64) {
+    polygons
+        .iter()
+        .filter_map(|rings| rings.first())
+        .flatten()
+        .fold(
+            (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+            |(min_x, min_y, max_x, max_y), &(x, y)| {
+                (min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))
+            },
+        )
+}
+
+#[derive(Deserialize)]
+struct FeatureCollection {
+    features: Vec<Feature>,
+}
+
+#[derive(Deserialize)]
+struct Feature {
+    properties: Option<Map<String, Value>>,
+    geometry: Option<Geometry>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type")]
+enum Geometry {
+    Polygon {
+        coordinates: Vec<Vec<Vec<f64>>>,
+    },
+    MultiPolygon {
+        coordinates: Vec<Vec<Vec<Vec<f64>>>>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn config() -> BoundariesConfig {
+        BoundariesConfig {
+            path: PathBuf::from("boundaries.geojson"),
+            country_property: String::from("iso_a2"),
+            region_property: String::from("name"),
+        }
+    }
+
+    fn square(min_x: f64, min_y: f64, max_x: f64, max_y: f64) -> Value {
+        serde_json::json!([
+            [min_x, min_y],
+            [max_x, min_y],
+            [max_x, max_y],
+            [min_x, max_y],
+            [min_x, min_y]
+        ])
+    }
+
+    fn feature(country: &str, region: &str, geometry: Value) -> Value {
+        serde_json::json!({
+            "type": "Feature",
+            "properties": { "iso_a2": country, "name": region },
+            "geometry": geometry,
+        })
+    }
+
+    fn geocoder(features: Vec<Value>) -> ReverseGeocoder {
+        let collection = serde_json::json!({ "type": "FeatureCollection", "features": features });
+        ReverseGeocoder::parse(&collection.to_string(), &config()).unwrap()
+    }
+
+    fn region(geocoder: &ReverseGeocoder, latitude: f64, longitude: f64) -> Option<&str> {
+        geocoder
+            .locate(&Position::new(latitude, longitude))
+            .and_then(|area| area.region.as_deref())
+    }
+
+    #[test]
+    fn excludes_the_holes_of_a_polygon() {
+        let geocoder = geocoder(vec![
+            feature(
+                "ZA",
+                "Free State",
+                serde_json::json!({
+                    "type": "Polygon",
+                    "coordinates": [square(0.0, 0.0, 10.0, 10.0), square(4.0, 4.0, 6.0, 6.0)],
+                }),
+            ),
+            feature(
+                "LS",
+                "Maseru",
+                serde_json::json!({ "type": "Polygon", "coordinates": [square(4.0, 4.0, 6.0, 6.0)] }),
+            ),
+        ]);
+
+        assert_eq!(region(&geocoder, 2.0, 2.0), Some("Free State"));
+        assert_eq!(region(&geocoder, 5.0, 5.0), Some("Maseru"));
+        assert_eq!(region(&geocoder, 11.0, 5.0), None);
+    }
+
+    #[test]
+    fn locates_every_part_of_a_multi_polygon() {
+        let geocoder = geocoder(vec![feature(
+            "NL",
+            "Friesland",
+            serde_json::json!({
+                "type": "MultiPolygon",
+                "coordinates": [[square(5.0, 53.0, 6.0, 53.3)], [square(5.0, 53.4, 5.5, 53.5)]],
+            }),
+        )]);
+
+        assert_eq!(region(&geocoder, 53.1, 5.5), Some("Friesland"));
+        assert_eq!(region(&geocoder, 53.45, 5.2), Some("Friesland"));
+        assert_eq!(region(&geocoder, 53.35, 5.2), None);
+    }
+
+    #[test]
+    fn locates_positions_on_edges_and_the_antimeridian() {
+        let geocoder = geocoder(vec![
+            feature(
+                "NL",
+                "Utrecht",
+                serde_json::json!({ "type": "Polygon", "coordinates": [square(4.0, 52.0, 5.0, 53.0)] }),
+            ),
+            feature(
+                "NL",
+                "Gelderland",
+                serde_json::json!({ "type": "Polygon", "coordinates": [square(5.0, 52.0, 6.0, 53.0)] }),
+            ),
+            feature(
+                "FJ",
+                "Northern",
+                serde_json::json!({
+                    "type": "MultiPolygon",
+                    "coordinates": [
+                        [square(178.0, -17.0, 180.0, -16.0)],
+                        [square(-180.0, -17.0, -179.0, -16.0)]
+                    ],
+                }),
+            ),
+        ]);
+
+        // A position on a shared edge is in exactly one of the areas
+        assert_eq!(region(&geocoder, 52.5, 5.0), Some("Gelderland"));
+        assert_eq!(region(&geocoder, 52.0, 4.5), Some("Utrecht"));
+
+        assert_eq!(region(&geocoder, -16.5, 180.0), Some("Northern"));
+        assert_eq!(region(&geocoder, -16.5, -180.0), Some("Northern"));
+        assert_eq!(region(&geocoder, -16.5, 179.5), Some("Northern"));
+        assert_eq!(region(&geocoder, -16.5, -179.5), Some("Northern"));
+    }
+
+    #[test]
+    fn ignores_codes_that_are_not_countries() {
+        let geocoder = geocoder(vec![feature(
+            "-99",
+            "Northern Cyprus",
+            serde_json::json!({ "type": "Polygon", "coordinates": [square(33.0, 35.0, 34.0, 35.5)] }),
+        )]);
+
+        let area = geocoder.locate(&Position::new(35.2, 33.5)).unwrap();
+        assert_eq!(area.country, None);
+        assert_eq!(area.region.as_deref(), Some("Northern Cyprus"));
+    }
+
+    #[test]
+    fn leaves_a_location_outside_every_area_unchanged() {
+        let geocoder = geocoder(vec![feature(
+            "NL",
+            "Utrecht",
+            serde_json::json!({ "type": "Polygon", "coordinates": [square(4.0, 52.0, 5.0, 53.0)] }),
+        )]);
+
+        let mut location = Location {
+            position: Some(Position::new(0.0, 0.0)),
+            name: None,
+            notes: None,
+            country: Some(String::from("NL")),
+            region: Some(String::from("Utrecht")),
+            address: None,
+        };
+        let original = location.clone();
+
+        assert!(!geocoder.correct(&mut location));
+        assert_eq!(location, original);
+
+        location.position = Some(Position::new(52.5, 4.5));
+        location.region = Some(String::from("Noord-Holland"));
+        assert!(geocoder.correct(&mut location));
+        assert_eq!(location.region.as_deref(), Some("Utrecht"));
+    }
+
+    #[test]
+    fn locates_the_time_zone_of_a_position() {
+        let feature = |tzid: &str, ring: Value| {
+            serde_json::json!({
+                "type": "Feature",
+                "properties": { "tzid": tzid },
+                "geometry": { "type": "Polygon", "coordinates": [ring] },
+            })
+        };
+        let collection = serde_json::json!({
+            "type": "FeatureCollection",
+            "features": [
+                feature("Europe/Amsterdam", square(3.0, 50.0, 7.0, 54.0)),
+                feature("Mars/Olympus_Mons", square(-10.0, 50.0, 0.0, 54.0)),
+            ],
+        });
+        let config = TimeZonesConfig {
+            path: PathBuf::from("timezones.geojson"),
+            property: String::from("tzid"),
+        };
+        let locator = TimeZoneLocator::parse(&collection.to_string(), &config).unwrap();
+
+        assert_eq!(
+            locator.locate(&Position::new(52.1, 5.1)),
+            Some(chrono_tz::Europe::Amsterdam)
+        );
+        // Unknown time zones are left out
+        assert_eq!(locator.locate(&Position::new(52.1, -5.0)), None);
+        assert_eq!(locator.locate(&Position::new(0.0, 0.0)), None);
+    }
+}
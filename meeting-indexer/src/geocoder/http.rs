@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+
+use crate::config::GeocoderConfig;
+use crate::geocoder::{Geocode, GeocodeError, GeocodeResult, Geocoder};
+use crate::meeting::Position;
+use futures_util::future::BoxFuture;
+use serde_json::Value;
+
+/// Any geocoder that takes the query as a URL parameter and answers with JSON. Where the
+/// position is in the response is configured with JSON pointers, like `/results/0/lat`.
+pub struct HttpGeocoder {
+    client: reqwest::Client,
+    url: String,
+    query_param: String,
+    params: BTreeMap<String, String>,
+    latitude_pointer: String,
+    longitude_pointer: String,
+    precision_pointer: Option<String>,
+    confidence_pointer: Option<String>,
+    country_code_pointer: Option<String>,
+}
+
+impl HttpGeocoder {
+    pub fn from_config(client: reqwest::Client, config: &GeocoderConfig) -> Result<Self, String> {
+        let url = config
+            .base_url
+            .as_deref()
+            .ok_or("the http geocoder needs a base_url")?;
+        let latitude_pointer = config
+            .latitude
+            .as_deref()
+            .ok_or("the http geocoder needs a latitude pointer")?;
+        let longitude_pointer = config
+            .longitude
+            .as_deref()
+            .ok_or("the http geocoder needs a longitude pointer")?;
+
+        let mut params = config.params.clone();
+
+        if let Some(api_key) = &config.api_key {
+            let api_key_param = config.api_key_param.as_deref().unwrap_or("key");
+            params.insert(api_key_param.to_string(), api_key.clone());
+        }
+
+        Ok(Self {
+            client,
+            url: url.to_string(),
+            query_param: config.query_param.clone().unwrap_or_else(|| "q".into()),
+            params,
+            latitude_pointer: latitude_pointer.to_string(),
+            longitude_pointer: longitude_pointer.to_string(),
+            precision_pointer: config.precision.clone(),
+            confidence_pointer: config.confidence.clone(),
+            country_code_pointer: config.country_code.clone(),
+        })
+    }
+
+    async fn search_api(&self, query: &str) -> GeocodeResult {
+        let response: Value = self
+            .client
+            .get(&self.url)
+            .query(&self.params)
+            .query(&[(&self.query_param, query)])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let latitude = response.pointer(&self.latitude_pointer);
+        let longitude = response.pointer(&self.longitude_pointer);
+
+        let position = match (latitude, longitude) {
+            // Nothing at the pointers means nothing was found
+            (None, None) => return Ok(None),
+            (Some(latitude), Some(longitude)) => {
+                match (parse_number(latitude), parse_number(longitude)) {
+                    (Some(latitude), Some(longitude)) => Position::new(latitude, longitude),
+                    _ => {
+                        return Err(GeocodeError::UnexpectedResponse(format!(
+                            "invalid position {latitude}, {longitude}"
+                        )))
+                    }
+                }
+            }
+            _ => {
+                return Err(GeocodeError::UnexpectedResponse(
+                    "the response has only one coordinate".into(),
+                ))
+            }
+        };
+
+        let value = |pointer: &Option<String>| {
+            pointer
+                .as_deref()
+                .and_then(|pointer| response.pointer(pointer))
+        };
+
+        let precision = value(&self.precision_pointer)
+            .and_then(Value::as_str)
+            .and_then(|precision| precision.parse().ok());
+        let country_code = value(&self.country_code_pointer).and_then(Value::as_str);
+
+        Ok(Some(Geocode {
+            confidence: value(&self.confidence_pointer).and_then(parse_number),
+            ..Geocode::new(position, precision).with_country(country_code, None)
+        }))
+    }
+}
+
+impl Geocoder for HttpGeocoder {
+    fn name(&self) -> &str {
+        "http"
+    }
+
+    fn search<'a>(&'a self, query: &'a str) -> BoxFuture<'a, GeocodeResult> {
+        Box::pin(self.search_api(query))
+    }
+}
+
+/// Numbers are numbers in some responses and strings in others.
+fn parse_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(string) => string.parse().ok(),
+        _ => None,
+    }
+}
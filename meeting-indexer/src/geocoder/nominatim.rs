@@ -0,0 +1,101 @@
+use crate::geocoder::{Geocode, GeocodeError, GeocodeResult, Geocoder, Precision};
+use crate::meeting::Position;
+use futures_util::future::BoxFuture;
+use serde::Deserialize;
+
+const DEFAULT_BASE_URL: &str = "https://nominatim.openstreetmap.org";
+
+/// The geocoder of OpenStreetMap, either the public instance or a self-hosted one.
+pub struct Nominatim {
+    client: reqwest::Client,
+    base_url: String,
+    /// Hosted Nominatim services expect a key, the public instance does not.
+    api_key: Option<String>,
+}
+
+impl Nominatim {
+    pub fn new(client: reqwest::Client, base_url: Option<&str>, api_key: Option<&str>) -> Self {
+        Self {
+            client,
+            base_url: base_url
+                .unwrap_or(DEFAULT_BASE_URL)
+                .trim_end_matches('/')
+                .to_string(),
+            api_key: api_key.map(str::to_string),
+        }
+    }
+
+    async fn search_api(&self, query: &str) -> GeocodeResult {
+        let mut request = self
+            .client
+            .get(format!("{}/search", self.base_url))
+            .query(&[
+                ("q", query),
+                ("format", "jsonv2"),
+                ("addressdetails", "1"),
+                ("limit", "1"),
+            ]);
+
+        if let Some(api_key) = &self.api_key {
+            request = request.query(&[("key", api_key)]);
+        }
+
+        let results: Vec<ApiRecord> = request.send().await?.error_for_status()?.json().await?;
+
+        let Some(record) = results.into_iter().next() else {
+            return Ok(None);
+        };
+
+        let position = match (record.lat.parse(), record.lon.parse()) {
+            (Ok(latitude), Ok(longitude)) => Position::new(latitude, longitude),
+            _ => {
+                return Err(GeocodeError::UnexpectedResponse(format!(
+                    "invalid position {}, {}",
+                    record.lat, record.lon
+                )))
+            }
+        };
+
+        let address = record.address.unwrap_or_default();
+
+        Ok(Some(
+            Geocode::new(position, record.place_rank.map(precision))
+                .with_country(address.country_code.as_deref(), address.country.as_deref()),
+        ))
+    }
+}
+
+impl Geocoder for Nominatim {
+    fn name(&self) -> &str {
+        "nominatim"
+    }
+
+    fn search<'a>(&'a self, query: &'a str) -> BoxFuture<'a, GeocodeResult> {
+        Box::pin(self.search_api(query))
+    }
+}
+
+/// See https://nominatim.org/release-docs/latest/customize/Ranking/
+fn precision(place_rank: u8) -> Precision {
+    match place_rank {
+        28.. => Precision::Rooftop,
+        26..=27 => Precision::Street,
+        13..=25 => Precision::City,
+        5..=12 => Precision::Region,
+        _ => Precision::Country,
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ApiRecord {
+    lat: String,
+    lon: String,
+    place_rank: Option<u8>,
+    address: Option<ApiAddress>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct ApiAddress {
+    country: Option<String>,
+    country_code: Option<String>,
+}
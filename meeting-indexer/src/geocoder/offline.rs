@@ -0,0 +1,667 @@
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use crate::geocoder::{Geocode, GeocodeResult, Geocoder, Precision};
+use crate::meeting::Position;
+use crate::migration::{migrate, MigrationError};
+use crate::position_lookup::escape_like;
+use futures_util::future::BoxFuture;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use thiserror::Error;
+
+/// The schema of gazetteer.db, one entry per version.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE addresses (
+        street TEXT NOT NULL,
+        number TEXT NOT NULL,
+        city TEXT NOT NULL,
+        postcode TEXT NULL,
+        latitude REAL NOT NULL,
+        longitude REAL NOT NULL
+    );
+    CREATE INDEX addresses_city ON addresses(city, street, number);
+
+    CREATE TABLE postcodes (
+        country TEXT NOT NULL,
+        postcode TEXT NOT NULL,
+        place TEXT NULL,
+        latitude REAL NOT NULL,
+        longitude REAL NOT NULL
+    );
+    CREATE INDEX postcodes_postcode ON postcodes(postcode);",
+    // Rows are kept per dump, so several dumps of the same format can be loaded side by side
+    "ALTER TABLE addresses ADD COLUMN dump TEXT NOT NULL DEFAULT '';
+    CREATE INDEX addresses_dump ON addresses(dump);
+    ALTER TABLE postcodes ADD COLUMN dump TEXT NOT NULL DEFAULT '';
+    CREATE INDEX postcodes_dump ON postcodes(dump);",
+];
+
+#[derive(Debug, Error)]
+pub enum GazetteerError {
+    #[error("SQL error: {0}")]
+    SqliteError(#[from] rusqlite::Error),
+
+    #[error("Migration error: {0}")]
+    MigrationError(#[from] MigrationError),
+
+    #[error("CSV error: {0}")]
+    CsvError(#[from] csv::Error),
+
+    #[error("Invalid record on line {line}: {reason}")]
+    InvalidRecord { line: u64, reason: String },
+}
+
+/// The dumps that can be loaded into the gazetteer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GazetteerFormat {
+    /// The CSV files of OpenAddresses, with a header like `LON,LAT,NUMBER,STREET,...`.
+    OpenAddresses,
+    /// The tab separated postal codes of GeoNames, like `NL.txt` from
+    /// https://download.geonames.org/export/zip/.
+    GeonamesPostal,
+}
+
+impl fmt::Display for GazetteerFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GazetteerFormat::OpenAddresses => write!(f, "openaddresses"),
+            GazetteerFormat::GeonamesPostal => write!(f, "geonames-postal"),
+        }
+    }
+}
+
+impl FromStr for GazetteerFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "openaddresses" => Ok(GazetteerFormat::OpenAddresses),
+            "geonames-postal" => Ok(GazetteerFormat::GeonamesPostal),
+            _ => Err(format!(
+                "unknown format {s}, expected openaddresses or geonames-postal"
+            )),
+        }
+    }
+}
+
+/// Resolves addresses and postal codes from a local dump, so no network is needed.
+pub struct OfflineGeocoder {
+    conn: Mutex<Connection>,
+}
+
+impl OfflineGeocoder {
+    pub fn open(path: &Path) -> Result<Self, GazetteerError> {
+        let mut conn = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_CREATE | OpenFlags::SQLITE_OPEN_READ_WRITE,
+        )?;
+
+        migrate(&mut conn, MIGRATIONS)?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Load a dump, replacing what was loaded before from the dump with the same name. Returns
+    /// the number of records that were loaded.
+    pub fn load(
+        &self,
+        format: GazetteerFormat,
+        dump: &str,
+        input: impl Read,
+    ) -> Result<usize, GazetteerError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        let count = match format {
+            GazetteerFormat::OpenAddresses => load_openaddresses(&tx, dump, input)?,
+            GazetteerFormat::GeonamesPostal => load_geonames_postal(&tx, dump, input)?,
+        };
+
+        tx.commit()?;
+
+        Ok(count)
+    }
+
+    fn search_sync(&self, query: &str) -> Result<Option<Geocode>, rusqlite::Error> {
+        let conn = self.conn.lock().unwrap();
+        let query = normalize(query);
+
+        if let Some(address) = AddressQuery::parse(&query) {
+            if let Some(geocode) = search_address(&conn, &address)? {
+                return Ok(Some(geocode));
+            }
+        }
+
+        search_postcode(&conn, &query)
+    }
+}
+
+impl Geocoder for OfflineGeocoder {
+    fn name(&self) -> &str {
+        "offline"
+    }
+
+    fn search<'a>(&'a self, query: &'a str) -> BoxFuture<'a, GeocodeResult> {
+        Box::pin(async move { Ok(self.search_sync(query)?) })
+    }
+}
+
+/// Lowercase words separated by single spaces, so queries and dumps compare equal.
+fn normalize(value: &str) -> String {
+    value
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// House numbers are written as `12a`, `12 A` and `12-a`.
+fn normalize_number(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+fn normalize_postcode(value: &str) -> String {
+    value.replace(' ', "").to_lowercase()
+}
+
+/// A query like `street number city`, or `street number postcode city`.
+struct AddressQuery<'a> {
+    street: &'a str,
+    number: String,
+    /// The words after the number, and without the one or two words of a postcode when they
+    /// start with one.
+    cities: Vec<&'a str>,
+}
+
+impl<'a> AddressQuery<'a> {
+    fn parse(query: &'a str) -> Option<Self> {
+        let words: Vec<&str> = query.split(' ').collect();
+        let has_digit = |word: &str| word.contains(|c: char| c.is_ascii_digit());
+
+        // The number is the first word after the street that starts with a digit
+        let number_index = words
+            .iter()
+            .skip(1)
+            .position(|word| word.starts_with(|c: char| c.is_ascii_digit()))?
+            + 1;
+
+        if number_index + 1 >= words.len() {
+            return None;
+        }
+
+        // The byte offset where the word at `index` starts
+        let word_start = |index: usize| words[..index].join(" ").len() + 1;
+
+        let mut cities = vec![&query[word_start(number_index + 1)..]];
+
+        if has_digit(words[number_index + 1]) {
+            for skipped in 2..=3 {
+                if number_index + skipped < words.len() {
+                    cities.push(&query[word_start(number_index + skipped)..]);
+                }
+            }
+        }
+
+        Some(Self {
+            street: &query[..word_start(number_index) - 1],
+            number: normalize_number(words[number_index]),
+            cities,
+        })
+    }
+}
+
+/// The position of the address, or the middle of the street when the number is unknown.
+fn search_address(
+    conn: &Connection,
+    address: &AddressQuery,
+) -> Result<Option<Geocode>, rusqlite::Error> {
+    for city in &address.cities {
+        if let Some(geocode) = search_address_in(conn, address, city)? {
+            return Ok(Some(geocode));
+        }
+    }
+
+    Ok(None)
+}
+
+fn search_address_in(
+    conn: &Connection,
+    address: &AddressQuery,
+    city: &str,
+) -> Result<Option<Geocode>, rusqlite::Error> {
+    // Sources sometimes only give the last word of a street, like `hallstraat` for
+    // `van hallstraat`
+    let street_filter = "city = ?1 AND (street = ?2 OR street LIKE '% ' || ?3 ESCAPE '\\')";
+    let street_pattern = escape_like(address.street);
+
+    let position = conn
+        .query_row(
+            &format!(
+                "SELECT latitude, longitude FROM addresses WHERE {street_filter} AND number = ?4"
+            ),
+            params![city, address.street, street_pattern, address.number],
+            |row| Ok(Position::new(row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+
+    if let Some(position) = position {
+        return Ok(Some(Geocode::new(position, Some(Precision::Rooftop))));
+    }
+
+    let (latitude, longitude): (Option<f64>, Option<f64>) = conn.query_row(
+        &format!("SELECT avg(latitude), avg(longitude) FROM addresses WHERE {street_filter}"),
+        params![city, address.street, street_pattern],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )?;
+
+    Ok(latitude.zip(longitude).map(|(latitude, longitude)| {
+        Geocode::new(Position::new(latitude, longitude), Some(Precision::Street))
+    }))
+}
+
+/// Whether a word looks like (the first part of) a postal code, like `1012`, `1012lg`, `sw1a` or
+/// `k1a`, and not like a house number such as `1` or `12a`.
+fn is_postcode_start(word: &str) -> bool {
+    let digits = word.chars().filter(char::is_ascii_digit).count();
+
+    if digits == 0 || !word.chars().all(|c| c.is_alphanumeric() || c == '-') {
+        return false;
+    }
+
+    if word.starts_with(|c: char| c.is_ascii_digit()) {
+        digits >= 4
+    } else {
+        word.len() >= 2
+    }
+}
+
+/// Whether a word looks like the second part of a postal code, like `lg` in `1012 lg` or `1aa`
+/// in `sw1a 1aa`.
+fn is_postcode_end(word: &str) -> bool {
+    (2..=4).contains(&word.len())
+        && word.chars().all(char::is_alphanumeric)
+        && word.contains(char::is_alphabetic)
+}
+
+/// Parts of a query that could be a postal code, like `1012ab` and `1012` in `1012 ab amsterdam`.
+/// Postal codes usually come after the street, so the candidates are ordered from the end of the
+/// query.
+fn postcode_candidates(query: &str) -> Vec<String> {
+    let words: Vec<&str> = query.split(' ').collect();
+    let mut candidates = Vec::new();
+
+    for start in (0..words.len()).rev() {
+        if !is_postcode_start(words[start]) {
+            continue;
+        }
+
+        if let Some(next) = words.get(start + 1).filter(|next| is_postcode_end(next)) {
+            candidates.push(normalize_postcode(&format!("{}{next}", words[start])));
+        }
+
+        candidates.push(normalize_postcode(words[start]));
+    }
+
+    candidates
+}
+
+/// The position of the first postal code in the query, unless it exists in more than one
+/// country.
+fn search_postcode(conn: &Connection, query: &str) -> Result<Option<Geocode>, rusqlite::Error> {
+    for candidate in postcode_candidates(query) {
+        let (countries, country, latitude, longitude): (usize, Option<String>, Option<f64>, Option<f64>) =
+            conn.query_row(
+                "SELECT count(DISTINCT country), min(country), avg(latitude), avg(longitude) FROM postcodes WHERE postcode = ?",
+                params![candidate],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )?;
+
+        if countries != 1 {
+            continue;
+        }
+
+        // How large the area of a postal code is differs per country, so it is at least a city
+        return Ok(latitude.zip(longitude).map(|(latitude, longitude)| {
+            Geocode::new(Position::new(latitude, longitude), Some(Precision::City))
+                .with_country(country.as_deref(), None)
+        }));
+    }
+
+    Ok(None)
+}
+
+fn parse_field<T: FromStr>(
+    record: &csv::StringRecord,
+    index: usize,
+    name: &str,
+) -> Result<T, GazetteerError> {
+    let line = record.position().map(|p| p.line()).unwrap_or_default();
+    let value = record.get(index).unwrap_or_default();
+
+    value
+        .trim()
+        .parse()
+        .map_err(|_| GazetteerError::InvalidRecord {
+            line,
+            reason: format!("invalid {name} {value:?}"),
+        })
+}
+
+fn load_openaddresses(
+    tx: &Connection,
+    dump: &str,
+    input: impl Read,
+) -> Result<usize, GazetteerError> {
+    let mut reader = csv::Reader::from_reader(input);
+
+    let headers = reader.headers()?.clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|header| header.eq_ignore_ascii_case(name))
+            .ok_or_else(|| GazetteerError::InvalidRecord {
+                line: 1,
+                reason: format!("missing column {name}"),
+            })
+    };
+
+    let (lon, lat, number, street, city, postcode) = (
+        column("LON")?,
+        column("LAT")?,
+        column("NUMBER")?,
+        column("STREET")?,
+        column("CITY")?,
+        column("POSTCODE")?,
+    );
+
+    tx.execute("DELETE FROM addresses WHERE dump = ?", params![dump])?;
+
+    let mut stmt = tx.prepare(
+        "INSERT INTO addresses (street, number, city, postcode, latitude, longitude, dump) VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )?;
+    let mut count = 0;
+
+    for record in reader.records() {
+        let record = record?;
+        let field = |index: usize| record.get(index).unwrap_or_default();
+
+        // Without these the address can never match a query
+        if field(street).is_empty() || field(number).is_empty() || field(city).is_empty() {
+            continue;
+        }
+
+        let latitude: f64 = parse_field(&record, lat, "latitude")?;
+        let longitude: f64 = parse_field(&record, lon, "longitude")?;
+        let postcode = Some(normalize_postcode(field(postcode))).filter(|p| !p.is_empty());
+
+        stmt.execute(params![
+            normalize(field(street)),
+            normalize_number(field(number)),
+            normalize(field(city)),
+            postcode,
+            latitude,
+            longitude,
+            dump
+        ])?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+fn load_geonames_postal(
+    tx: &Connection,
+    dump: &str,
+    input: impl Read,
+) -> Result<usize, GazetteerError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(b'\t')
+        .has_headers(false)
+        .quoting(false)
+        .flexible(true)
+        .from_reader(input);
+
+    tx.execute("DELETE FROM postcodes WHERE dump = ?", params![dump])?;
+
+    let mut stmt = tx.prepare(
+        "INSERT INTO postcodes (country, postcode, place, latitude, longitude, dump) VALUES (?, ?, ?, ?, ?, ?)",
+    )?;
+    let mut count = 0;
+
+    for record in reader.records() {
+        let record = record?;
+
+        // country code, postal code, place name, 3 admin names and codes, latitude, longitude
+        let latitude: f64 = parse_field(&record, 9, "latitude")?;
+        let longitude: f64 = parse_field(&record, 10, "longitude")?;
+
+        stmt.execute(params![
+            record.get(0).unwrap_or_default(),
+            normalize_postcode(record.get(1).unwrap_or_default()),
+            record.get(2).filter(|place| !place.is_empty()),
+            latitude,
+            longitude,
+            dump
+        ])?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gazetteer() -> OfflineGeocoder {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, MIGRATIONS).unwrap();
+
+        OfflineGeocoder {
+            conn: Mutex::new(conn),
+        }
+    }
+
+    fn parse(query: &str) -> Option<(String, String, Vec<String>)> {
+        let query = normalize(query);
+
+        AddressQuery::parse(&query).map(|address| {
+            (
+                address.street.to_string(),
+                address.number,
+                address.cities.iter().map(|city| city.to_string()).collect(),
+            )
+        })
+    }
+
+    fn address(
+        street: &str,
+        number: &str,
+        cities: &[&str],
+    ) -> Option<(String, String, Vec<String>)> {
+        Some((
+            street.to_string(),
+            number.to_string(),
+            cities.iter().map(|city| city.to_string()).collect(),
+        ))
+    }
+
+    #[test]
+    fn parses_an_address() {
+        assert_eq!(
+            parse("Damrak 1, Amsterdam"),
+            address("damrak", "1", &["amsterdam"])
+        );
+        assert_eq!(
+            parse("Van Hallstraat 12-A  Den Haag"),
+            address("van hallstraat", "12a", &["den haag"])
+        );
+    }
+
+    #[test]
+    fn skips_a_postcode_before_the_city() {
+        assert_eq!(
+            parse("Damrak 1 1012 LG Amsterdam"),
+            address(
+                "damrak",
+                "1",
+                &["1012 lg amsterdam", "lg amsterdam", "amsterdam"]
+            )
+        );
+        assert_eq!(
+            parse("Damrak 1 1012LG Amsterdam"),
+            address("damrak", "1", &["1012lg amsterdam", "amsterdam"])
+        );
+    }
+
+    #[test]
+    fn allows_a_street_starting_with_a_number() {
+        assert_eq!(
+            parse("2e Hugo de Grootstraat 5 Amsterdam"),
+            address("2e hugo de grootstraat", "5", &["amsterdam"])
+        );
+    }
+
+    #[test]
+    fn needs_a_number_and_a_city() {
+        assert_eq!(parse("Damrak Amsterdam"), None);
+        assert_eq!(parse("Damrak 1"), None);
+        assert_eq!(parse("1012 LG"), None);
+    }
+
+    #[test]
+    fn finds_postcode_candidates_from_the_end() {
+        assert_eq!(
+            postcode_candidates("damrak 1 1012 lg amsterdam"),
+            vec!["1012lg", "1012"]
+        );
+        assert_eq!(
+            postcode_candidates("baker street 221b london nw1 6xe"),
+            vec!["nw16xe", "nw1"]
+        );
+        assert_eq!(
+            postcode_candidates("hauptstrasse 12 10115 berlin"),
+            vec!["10115"]
+        );
+        assert!(postcode_candidates("damrak 1 amsterdam").is_empty());
+    }
+
+    #[test]
+    fn loads_dumps_side_by_side() {
+        let gazetteer = gazetteer();
+        let amsterdam = "NL\t1012\tAmsterdam\t\t\t\t\t\t\t52.37\t4.89\t\n";
+        let brussels = "BE\t1000\tBrussel\t\t\t\t\t\t\t50.85\t4.35\t\n";
+
+        gazetteer
+            .load(
+                GazetteerFormat::GeonamesPostal,
+                "NL.txt",
+                amsterdam.as_bytes(),
+            )
+            .unwrap();
+        gazetteer
+            .load(
+                GazetteerFormat::GeonamesPostal,
+                "BE.txt",
+                brussels.as_bytes(),
+            )
+            .unwrap();
+        gazetteer
+            .load(
+                GazetteerFormat::GeonamesPostal,
+                "NL.txt",
+                amsterdam.as_bytes(),
+            )
+            .unwrap();
+
+        let count: usize = gazetteer
+            .conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT count(*) FROM postcodes", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 2);
+
+        let geocode = gazetteer.search_sync("1000 Brussel").unwrap().unwrap();
+        assert_eq!(geocode.position, Position::new(50.85, 4.35));
+        assert_eq!(geocode.country_code.as_deref(), Some("BE"));
+    }
+
+    #[test]
+    fn matches_the_last_words_of_a_street_literally() {
+        let gazetteer = gazetteer();
+        let addresses =
+            "LON,LAT,NUMBER,STREET,CITY,POSTCODE\n4.88,52.38,1,Van Hallstraat,Amsterdam,\n";
+
+        gazetteer
+            .load(
+                GazetteerFormat::OpenAddresses,
+                "nl.csv",
+                addresses.as_bytes(),
+            )
+            .unwrap();
+
+        let geocode = gazetteer
+            .search_sync("Hallstraat 1, Amsterdam")
+            .unwrap()
+            .unwrap();
+        assert_eq!(geocode.position, Position::new(52.38, 4.88));
+
+        for query in ["H_llstraat 1, Amsterdam", "%straat 1, Amsterdam"] {
+            assert_eq!(gazetteer.search_sync(query).unwrap(), None, "{query}");
+        }
+    }
+
+    #[test]
+    fn finds_an_address_with_a_postcode() {
+        let gazetteer = gazetteer();
+        let addresses =
+            "LON,LAT,NUMBER,STREET,CITY,POSTCODE\n4.89,52.37,1,Damrak,Amsterdam,1012 LG\n";
+
+        gazetteer
+            .load(
+                GazetteerFormat::OpenAddresses,
+                "nl.csv",
+                addresses.as_bytes(),
+            )
+            .unwrap();
+
+        let geocode = gazetteer
+            .search_sync("Damrak 1, 1012 LG Amsterdam")
+            .unwrap()
+            .unwrap();
+        assert_eq!(geocode.position, Position::new(52.37, 4.89));
+        assert_eq!(geocode.precision, Some(Precision::Rooftop));
+    }
+
+    #[test]
+    fn finds_the_postcode_in_an_address() {
+        let gazetteer = gazetteer();
+        let postcodes = "NL\t1012 LG\tAmsterdam\t\t\t\t\t\t\t52.37\t4.89\t\n";
+
+        gazetteer
+            .load(
+                GazetteerFormat::GeonamesPostal,
+                "NL.txt",
+                postcodes.as_bytes(),
+            )
+            .unwrap();
+
+        let geocode = gazetteer
+            .search_sync("Damrak 1, 1012 LG Amsterdam")
+            .unwrap()
+            .unwrap();
+        assert_eq!(geocode.position, Position::new(52.37, 4.89));
+        assert_eq!(geocode.precision, Some(Precision::City));
+    }
+}
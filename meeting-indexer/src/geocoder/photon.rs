@@ -0,0 +1,104 @@
+use crate::geocoder::{Geocode, GeocodeResult, Geocoder, Precision};
+use crate::meeting::Position;
+use futures_util::future::BoxFuture;
+use serde::Deserialize;
+
+const DEFAULT_BASE_URL: &str = "https://photon.komoot.io";
+
+/// The Photon geocoder, usually self-hosted on top of an OpenStreetMap import.
+pub struct Photon {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl Photon {
+    pub fn new(client: reqwest::Client, base_url: Option<&str>) -> Self {
+        Self {
+            client,
+            base_url: base_url
+                .unwrap_or(DEFAULT_BASE_URL)
+                .trim_end_matches('/')
+                .to_string(),
+        }
+    }
+
+    async fn search_api(&self, query: &str) -> GeocodeResult {
+        let data: ApiData = self
+            .client
+            .get(format!("{}/api", self.base_url))
+            .query(&[("q", query), ("limit", "1")])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let Some(feature) = data.features.into_iter().next() else {
+            return Ok(None);
+        };
+
+        // GeoJSON puts the longitude first
+        let [longitude, latitude] = feature.geometry.coordinates;
+
+        let properties = feature.properties;
+        let precision = if properties.housenumber.is_some() {
+            Some(Precision::Rooftop)
+        } else {
+            properties.place_type.as_deref().and_then(precision)
+        };
+
+        Ok(Some(
+            Geocode::new(Position::new(latitude, longitude), precision).with_country(
+                properties.countrycode.as_deref(),
+                properties.country.as_deref(),
+            ),
+        ))
+    }
+}
+
+impl Geocoder for Photon {
+    fn name(&self) -> &str {
+        "photon"
+    }
+
+    fn search<'a>(&'a self, query: &'a str) -> BoxFuture<'a, GeocodeResult> {
+        Box::pin(self.search_api(query))
+    }
+}
+
+fn precision(place_type: &str) -> Option<Precision> {
+    match place_type {
+        "house" => Some(Precision::Rooftop),
+        "street" => Some(Precision::Street),
+        "city" | "district" | "locality" => Some(Precision::City),
+        "county" | "state" => Some(Precision::Region),
+        "country" => Some(Precision::Country),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ApiData {
+    features: Vec<ApiFeature>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ApiFeature {
+    geometry: ApiGeometry,
+    #[serde(default)]
+    properties: ApiProperties,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+struct ApiProperties {
+    #[serde(rename = "type")]
+    place_type: Option<String>,
+    housenumber: Option<String>,
+    country: Option<String>,
+    countrycode: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ApiGeometry {
+    coordinates: [f64; 2],
+}
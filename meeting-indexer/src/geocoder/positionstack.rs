@@ -0,0 +1,103 @@
+use crate::geocoder::{Geocode, GeocodeError, GeocodeResult, Geocoder, Precision};
+use crate::meeting::Position;
+use futures_util::future::BoxFuture;
+use serde::Deserialize;
+use serde_json::Value;
+
+const DEFAULT_BASE_URL: &str = "https://api.positionstack.com/v1";
+
+pub struct Positionstack {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: String,
+}
+
+impl Positionstack {
+    pub fn new(client: reqwest::Client, base_url: Option<&str>, api_key: &str) -> Self {
+        Self {
+            client,
+            base_url: base_url
+                .unwrap_or(DEFAULT_BASE_URL)
+                .trim_end_matches('/')
+                .to_string(),
+            api_key: api_key.to_string(),
+        }
+    }
+
+    async fn search_api(&self, query: &str) -> GeocodeResult {
+        let data: ApiData = self
+            .client
+            .get(format!("{}/forward", self.base_url))
+            .query(&[
+                ("access_key", self.api_key.as_str()),
+                ("query", query),
+                ("limit", "1"),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        if let Some(error) = data.error {
+            return Err(GeocodeError::UnexpectedResponse(error.to_string()));
+        }
+
+        // Without results `data` is sometimes an empty list inside a list
+        let record = data
+            .data
+            .into_iter()
+            .find_map(|record| serde_json::from_value::<ApiRecord>(record).ok());
+
+        Ok(record.map(|record| {
+            let position = Position::new(record.latitude, record.longitude);
+            let precision = record.place_type.as_deref().and_then(precision);
+
+            // The country code is alpha-3, so only the name is of use
+            Geocode {
+                confidence: record.confidence,
+                ..Geocode::new(position, precision).with_country(None, record.country.as_deref())
+            }
+        }))
+    }
+}
+
+impl Geocoder for Positionstack {
+    fn name(&self) -> &str {
+        "positionstack"
+    }
+
+    fn search<'a>(&'a self, query: &'a str) -> BoxFuture<'a, GeocodeResult> {
+        Box::pin(self.search_api(query))
+    }
+}
+
+fn precision(place_type: &str) -> Option<Precision> {
+    match place_type {
+        "address" | "venue" => Some(Precision::Rooftop),
+        "street" => Some(Precision::Street),
+        "neighbourhood" | "borough" | "locality" | "postalcode" => Some(Precision::City),
+        "localadmin" | "county" | "macrocounty" | "region" | "macroregion" => {
+            Some(Precision::Region)
+        }
+        "country" => Some(Precision::Country),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ApiData {
+    #[serde(default)]
+    data: Vec<Value>,
+    error: Option<Value>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ApiRecord {
+    latitude: f64,
+    longitude: f64,
+    #[serde(rename = "type")]
+    place_type: Option<String>,
+    confidence: Option<f64>,
+    country: Option<String>,
+}
@@ -3,12 +3,16 @@ use std::path::PathBuf;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::Duration;
 
-use rusqlite::{params, Connection, OpenFlags, ToSql, Transaction};
+use chrono::{DateTime, NaiveTime, Timelike, Utc};
+use rusqlite::functions::FunctionFlags;
+use rusqlite::{params, Connection, OpenFlags, Row, ToSql, Transaction};
 use serde::Serialize;
 use thiserror::Error;
 use utoipa::ToSchema;
 
 use crate::meeting::*;
+use crate::migration::{migrate, MigrationError};
+use crate::sync_report::{MeetingChange, ModifiedMeeting, SourceReport};
 
 pub struct DistanceSearch {
     pub latitude: f64,
@@ -16,54 +20,514 @@ pub struct DistanceSearch {
     pub distance: f64,
 }
 
+const EARTH_RADIUS: f64 = 6371.0;
+
+impl DistanceSearch {
+    /// The smallest box that contains every position within the distance, see
+    /// http://janmatuschek.de/LatitudeLongitudeBoundingCoordinates
+    pub fn bounding_box(&self) -> BoundingBox {
+        let radius = (self.distance / EARTH_RADIUS).to_degrees();
+
+        let south = self.latitude - radius;
+        let north = self.latitude + radius;
+
+        if south <= -90.0 || north >= 90.0 {
+            // The circle contains a pole, so it contains every longitude.
+            return BoundingBox {
+                south: south.max(-90.0),
+                west: -180.0,
+                north: north.min(90.0),
+                east: 180.0,
+            };
+        }
+
+        let delta = ((self.distance / EARTH_RADIUS).sin() / self.latitude.to_radians().cos())
+            .asin()
+            .to_degrees();
+
+        if delta.is_nan() || delta >= 180.0 {
+            return BoundingBox {
+                south,
+                west: -180.0,
+                north,
+                east: 180.0,
+            };
+        }
+
+        let wrap = |longitude: f64| {
+            if longitude < -180.0 {
+                longitude + 360.0
+            } else if longitude > 180.0 {
+                longitude - 360.0
+            } else {
+                longitude
+            }
+        };
+
+        BoundingBox {
+            south,
+            west: wrap(self.longitude - delta),
+            north,
+            east: wrap(self.longitude + delta),
+        }
+    }
+}
+
+/// The distance in kilometers between two positions, see
+/// https://stackoverflow.com/questions/27928/calculate-distance-between-two-latitude-longitude-points-haversine-formula
+fn haversine(from: &Position, to: &Position) -> f64 {
+    const P: f64 = 0.017453292519943295;
+
+    let a = 0.5 - ((to.latitude - from.latitude) * P).cos() / 2.0
+        + (from.latitude * P).cos()
+            * (to.latitude * P).cos()
+            * (1.0 - ((to.longitude - from.longitude) * P).cos())
+            / 2.0;
+
+    2.0 * EARTH_RADIUS * a.sqrt().asin()
+}
+
+/// Register the functions used in queries, so they do not depend on how SQLite was compiled.
+fn register_functions(conn: &Connection) -> rusqlite::Result<()> {
+    conn.create_scalar_function(
+        "haversine",
+        4,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let coordinates = (
+                ctx.get::<Option<f64>>(0)?,
+                ctx.get::<Option<f64>>(1)?,
+                ctx.get::<Option<f64>>(2)?,
+                ctx.get::<Option<f64>>(3)?,
+            );
+
+            Ok(match coordinates {
+                (Some(lat1), Some(long1), Some(lat2), Some(long2)) => Some(haversine(
+                    &Position::new(lat1, long1),
+                    &Position::new(lat2, long2),
+                )),
+                _ => None,
+            })
+        },
+    )
+}
+
+/// A rectangle between a south-west and north-east corner. When `west` is greater than `east`
+/// the box crosses the antimeridian.
+pub struct BoundingBox {
+    pub south: f64,
+    pub west: f64,
+    pub north: f64,
+    pub east: f64,
+}
+
 #[derive(Default)]
 pub struct SearchOptions {
     pub distance: Option<DistanceSearch>,
+    pub bounds: Option<BoundingBox>,
+    /// Only include meetings whose name, notes or location match this text, ordered by
+    /// relevance.
+    pub text: Option<String>,
+    /// Only include meetings that have all of these types.
+    pub types: Vec<MeetingType>,
+    /// Only include meetings with one of these attendance modes.
+    pub attendance: Vec<AttendanceMode>,
+    /// Only include meetings of one of these organizations.
+    pub orgs: Vec<Organization>,
+    /// Only include meetings on one of these days.
+    pub days: Vec<WeekDay>,
+    /// Only include meetings starting at or after this time of day.
+    pub start_after: Option<NaiveTime>,
+    /// Only include meetings starting at or before this time of day. When it is earlier than
+    /// `start_after` the window wraps around midnight.
+    pub start_before: Option<NaiveTime>,
+    pub country: Option<String>,
+    pub region: Option<String>,
+    /// The maximum number of meetings to return, all of them when `None`.
+    pub limit: Option<usize>,
+    pub offset: usize,
 }
 
 #[derive(Serialize, ToSchema)]
 pub struct SearchMeeting {
     pub meeting: Meeting,
     pub distance: Option<f64>,
+    /// When the meeting was first found during a sync.
+    pub first_seen: DateTime<Utc>,
+    /// When the meeting was last found during a sync.
+    pub last_seen: DateTime<Utc>,
+    /// Since when the source of the meeting could not be fetched or was held back, so the
+    /// meeting might be outdated.
+    pub stale_since: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SearchResults {
+    /// The number of meetings matching the search, across all pages.
+    pub total: usize,
+    pub offset: usize,
+    pub limit: Option<usize>,
+    pub meetings: Vec<SearchMeeting>,
 }
 
 #[derive(Error, Debug)]
 pub enum IndexError {
     #[error("SQL error: {0}")]
     SqliteError(#[from] rusqlite::Error),
+
+    #[error("Migration error: {0}")]
+    MigrationError(#[from] MigrationError),
+}
+
+/// The schema of meetings.db, one entry per version. Never change a migration that has been
+/// released, add a new one instead.
+const MIGRATIONS: &[&str] = &[
+    // Databases from before versioning already have these tables when they are in the latest
+    // layout, older layouts are dropped by `drop_outdated_layout`.
+    "CREATE TABLE IF NOT EXISTS meetings (
+        id TEXT NOT NULL PRIMARY KEY,
+        name TEXT NOT NULL,
+        org TEXT NOT NULL,
+        notes TEXT NULL,
+        source TEXT NOT NULL,
+        updated_at DATETIME NOT NULL,
+
+        country TEXT NULL,
+        region TEXT NULL,
+        location_name TEXT NULL,
+        location_notes TEXT NULL,
+        latitude REAL NULL,
+        longitude REAL NULL,
+        address TEXT NULL,
+
+        attendance TEXT NOT NULL,
+        online_url TEXT NULL,
+        online_notes TEXT NULL,
+
+        phone TEXT NULL,
+        email TEXT NULL,
+
+        duration INTEGER NULL,
+        recurrence TEXT NOT NULL DEFAULT 'weekly',
+        week INTEGER NULL,
+        date DATE NULL,
+        day INTEGER NULL,
+        hour INTEGER NULL,
+        minute INTEGER NULL,
+        timezone TEXT NULL
+    );
+
+    -- Keyed on the rowid of the meetings table
+    CREATE VIRTUAL TABLE IF NOT EXISTS meetings_rtree USING rtree(
+        id,
+        min_latitude, max_latitude,
+        min_longitude, max_longitude
+    );
+
+    CREATE TABLE IF NOT EXISTS meeting_types (
+        meeting TEXT NOT NULL,
+        type TEXT NOT NULL,
+        PRIMARY KEY (meeting, type)
+    );
+
+    CREATE INDEX IF NOT EXISTS meeting_types_type ON meeting_types(type, meeting);
+
+    CREATE VIRTUAL TABLE IF NOT EXISTS meetings_fts USING fts5(
+        id UNINDEXED,
+        name,
+        notes,
+        location_name,
+        location_notes,
+        address,
+        region,
+        tokenize = 'unicode61 remove_diacritics 2'
+    );",
+    "ALTER TABLE meetings ADD COLUMN first_seen DATETIME NULL;
+    ALTER TABLE meetings ADD COLUMN last_seen DATETIME NULL;
+    ALTER TABLE meetings ADD COLUMN removed_at DATETIME NULL;
+    UPDATE meetings SET first_seen = updated_at, last_seen = updated_at;",
+    // Meetings from before this version have no source, they are removed when a sync does
+    // not find them again.
+    "ALTER TABLE meetings ADD COLUMN source_key TEXT NULL;
+    ALTER TABLE meetings ADD COLUMN stale_since DATETIME NULL;
+    CREATE INDEX meetings_source_key ON meetings(source_key);",
+];
+
+fn table_columns(conn: &Connection, table: &str) -> Result<Vec<String>, rusqlite::Error> {
+    conn.prepare(&format!("PRAGMA table_info({table})"))?
+        .query_map([], |row| row.get("name"))?
+        .collect()
+}
+
+/// Drop the tables of a database from before versioning when the meetings table is missing
+/// columns of the first version, its rows can not be kept without an id. Returns whether
+/// anything was dropped.
+fn drop_outdated_layout(conn: &Connection) -> Result<bool, rusqlite::Error> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    let columns = table_columns(conn, "meetings")?;
+
+    if version > 0 || columns.is_empty() {
+        return Ok(false);
+    }
+
+    let first_version = Connection::open_in_memory()?;
+    first_version.execute_batch(MIGRATIONS[0])?;
+
+    if table_columns(&first_version, "meetings")?
+        .iter()
+        .all(|column| columns.contains(column))
+    {
+        return Ok(false);
+    }
+
+    conn.execute_batch(
+        "DROP TABLE IF EXISTS meetings;
+        DROP TABLE IF EXISTS meeting_types;
+        DROP TABLE IF EXISTS meetings_fts;
+        DROP TABLE IF EXISTS meetings_rtree;",
+    )?;
+
+    Ok(true)
+}
+
+/// The conditions and parameters of the WHERE clause of a query.
+#[derive(Default)]
+struct SqlFilter {
+    conditions: Vec<String>,
+    params: Vec<(String, Box<dyn ToSql>)>,
+}
+
+impl SqlFilter {
+    /// Add a parameter and return its generated name.
+    fn param(&mut self, value: impl ToSql + 'static) -> String {
+        let name = format!(":p{}", self.params.len());
+        self.named(&name, value);
+        name
+    }
+
+    fn named(&mut self, name: &str, value: impl ToSql + 'static) {
+        self.params.push((name.to_string(), Box::new(value)));
+    }
+
+    fn condition(&mut self, condition: String) {
+        self.conditions.push(condition);
+    }
+
+    /// Require `column` to be one of `values`, unless there are no values.
+    fn any_of<T: ToSql + 'static>(&mut self, column: &str, values: impl Iterator<Item = T>) {
+        let names: Vec<String> = values.map(|value| self.param(value)).collect();
+
+        if !names.is_empty() {
+            self.condition(format!("{column} IN ({})", names.join(", ")));
+        }
+    }
+
+    /// Require the position of a meeting to be inside `bounds`, using the R*Tree.
+    fn within(&mut self, bounds: &BoundingBox) {
+        let south = self.param(bounds.south);
+        let north = self.param(bounds.north);
+        let west = self.param(bounds.west);
+        let east = self.param(bounds.east);
+
+        let longitude_condition = if bounds.west <= bounds.east {
+            format!("max_longitude >= {west} AND min_longitude <= {east}")
+        } else {
+            format!("(max_longitude >= {west} OR min_longitude <= {east})")
+        };
+
+        self.condition(format!(
+            "meetings.rowid IN (SELECT id FROM meetings_rtree WHERE max_latitude >= {south} AND min_latitude <= {north} AND {longitude_condition})"
+        ));
+    }
+
+    fn where_clause(&self) -> String {
+        if self.conditions.is_empty() {
+            String::new()
+        } else {
+            format!("\nWHERE {}", self.conditions.join(" AND "))
+        }
+    }
+
+    fn params(&self) -> Vec<(&str, &dyn ToSql)> {
+        self.params
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_ref()))
+            .collect()
+    }
+}
+
+/// Turn user input into an FTS5 query that matches every word as a prefix, so that FTS5 syntax
+/// in the input cannot cause errors.
+fn fts_query(text: &str) -> String {
+    text.split_whitespace()
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn minute_of_day(time: NaiveTime) -> u32 {
+    time.hour() * 60 + time.minute()
+}
+
+fn meeting_time_from_row(row: &Row) -> rusqlite::Result<MeetingTime> {
+    let hour = row.get("hour")?;
+    let minute = row.get("minute")?;
+
+    Ok(match row.get::<_, String>("recurrence")?.as_str() {
+        "monthly" => MeetingTime::Monthly {
+            week: row.get("week")?,
+            day: WeekDay::from_day_index(row.get("day")?),
+            hour,
+            minute,
+        },
+        "biweekly" => MeetingTime::Biweekly {
+            anchor: row.get("date")?,
+            hour,
+            minute,
+        },
+        "once" => MeetingTime::Once {
+            date: row.get("date")?,
+            hour,
+            minute,
+        },
+        _ => MeetingTime::Recurring {
+            day: WeekDay::from_day_index(row.get("day")?),
+            hour,
+            minute,
+        },
+    })
+}
+
+/// The columns needed by [meeting_from_row].
+const MEETING_COLUMNS: &str =
+    "(SELECT GROUP_CONCAT(type, ',') FROM meeting_types WHERE meeting = meetings.id) as types, meetings.*";
+
+fn meeting_from_row(row: &Row) -> rusqlite::Result<Meeting> {
+    let position = match (row.get("latitude")?, row.get("longitude")?) {
+        (Some(latitude), Some(longitude)) => Some(Position {
+            latitude,
+            longitude,
+        }),
+        _ => None,
+    };
+
+    // TODO: handle parse errors
+
+    Ok(Meeting {
+        id: row.get("id")?,
+        name: row.get("name")?,
+        org: row.get::<_, String>("org")?.parse().unwrap(),
+        notes: row.get("notes")?,
+        source: row.get("source")?,
+        updated_at: row.get("updated_at")?,
+        contact: Contact {
+            email: row.get("email")?,
+            phone: row.get("phone")?,
+        },
+        location: Location {
+            position,
+            name: row.get("location_name")?,
+            notes: row.get("location_notes")?,
+            country: row.get("country")?,
+            region: row.get("region")?,
+            address: row.get("address")?,
+        },
+        attendance: row.get::<_, String>("attendance")?.parse().unwrap(),
+        online_options: OnlineOptions {
+            url: row.get("online_url")?,
+            notes: row.get("online_notes")?,
+        },
+        types: row
+            .get::<_, Option<String>>("types")?
+            .map(|types| {
+                let mut types: Vec<MeetingType> =
+                    types.split(',').filter_map(|t| t.parse().ok()).collect();
+                types.sort();
+                types
+            })
+            .unwrap_or_default(),
+        time: meeting_time_from_row(row)?,
+        timezone: row
+            .get::<_, Option<String>>("timezone")?
+            .and_then(|name| parse_timezone(&name)),
+        duration: row
+            .get::<_, Option<u64>>("duration")?
+            .map(Duration::from_secs),
+    })
 }
 
 pub struct MeetingImport<'index> {
     tx: Transaction<'index>,
+    started_at: DateTime<Utc>,
     total_meetings: AtomicUsize,
 }
 
+/// Whether the meetings of `source_key` belong to `source`, either directly or through one of
+/// its sub-sources.
+fn belongs_to_source(source_key: &str, source: &str) -> bool {
+    source_key == source
+        || source_key
+            .strip_prefix(source)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
 impl<'index> MeetingImport<'index> {
+    /// Add or update the meetings found in `source`, returning which of them are new or were
+    /// modified.
     pub async fn add_meetings(
         &self,
+        source: &str,
         meetings: impl Iterator<Item = &Meeting>,
-    ) -> Result<(), IndexError> {
+    ) -> Result<SourceReport, IndexError> {
         let mut meeting_count = 0;
+        let mut report = SourceReport::default();
 
         for meeting in meetings {
-            let meeting_day;
-            let meeting_hour;
-            let meeting_minute;
-
-            match &meeting.time {
-                MeetingTime::Recurring { day, hour, minute } => {
-                    meeting_day = Some(day);
-                    meeting_hour = Some(hour);
-                    meeting_minute = Some(minute);
+            let (recurrence, week, date) = match &meeting.time {
+                MeetingTime::Recurring { .. } => ("weekly", None, None),
+                MeetingTime::Monthly { week, .. } => ("monthly", Some(*week), None),
+                MeetingTime::Biweekly { anchor, .. } => ("biweekly", None, Some(*anchor)),
+                MeetingTime::Once { date, .. } => ("once", None, Some(*date)),
+            };
+
+            let previous = self.previous_meeting(&meeting.id)?;
+
+            match &previous {
+                None => report.added.push(meeting.id.clone()),
+                Some(previous) => {
+                    let changes = MeetingChange::between(previous, meeting);
+
+                    if !changes.is_empty() {
+                        report.modified.push(ModifiedMeeting {
+                            id: meeting.id.clone(),
+                            changes,
+                        });
+                    }
                 }
             }
 
+            // Only bump updated_at when something about the meeting actually changed.
+            let updated_at = match previous {
+                Some(previous) if previous.same_content(meeting) => previous.updated_at,
+                _ => meeting.updated_at,
+            };
+
             self.tx.execute(
-                "INSERT INTO meetings(updated_at, online, online_notes, source, latitude, longitude, location_name, location_notes, country, region, address, name, notes, org, online_url, phone, email, duration, day, hour, minute)
-                VALUES(?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                "INSERT INTO meetings(id, updated_at, attendance, online_notes, source, latitude, longitude, location_name, location_notes, country, region, address, name, notes, org, online_url, phone, email, duration, recurrence, week, date, day, hour, minute, timezone, first_seen, last_seen, source_key)
+                VALUES(?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?27, ?28)
+                ON CONFLICT(id) DO UPDATE SET
+                    updated_at = excluded.updated_at, attendance = excluded.attendance, online_notes = excluded.online_notes,
+                    source = excluded.source, latitude = excluded.latitude, longitude = excluded.longitude,
+                    location_name = excluded.location_name, location_notes = excluded.location_notes, country = excluded.country,
+                    region = excluded.region, address = excluded.address, name = excluded.name, notes = excluded.notes,
+                    org = excluded.org, online_url = excluded.online_url, phone = excluded.phone, email = excluded.email,
+                    duration = excluded.duration, recurrence = excluded.recurrence, week = excluded.week, date = excluded.date,
+                    day = excluded.day, hour = excluded.hour, minute = excluded.minute, timezone = excluded.timezone,
+                    last_seen = excluded.last_seen, source_key = excluded.source_key, removed_at = NULL, stale_since = NULL",
                 params![
-                    meeting.updated_at,
-                    meeting.online_options.is_online,
+                    meeting.id,
+                    updated_at,
+                    meeting.attendance.to_string(),
                     meeting.online_options.notes,
                     meeting.source,
                     meeting.location.position.as_ref().map(|p| p.latitude),
@@ -80,30 +544,218 @@ impl<'index> MeetingImport<'index> {
                     meeting.contact.phone,
                     meeting.contact.email,
                     meeting.duration.map(|d| d.as_secs()),
-                    meeting_day.map(|day| day.to_day_index()),
-                    meeting_hour,
-                    meeting_minute
+                    recurrence,
+                    week,
+                    date,
+                    meeting.time.day().to_day_index(),
+                    meeting.time.hour(),
+                    meeting.time.minute(),
+                    meeting.timezone.map(|tz| tz.name()),
+                    self.started_at,
+                    source
                 ])?;
 
+            let rowid: i64 = self.tx.query_row(
+                "SELECT rowid FROM meetings WHERE id = ?",
+                params![meeting.id],
+                |row| row.get(0),
+            )?;
+
+            self.tx
+                .execute("DELETE FROM meetings_rtree WHERE id = ?", params![rowid])?;
+
+            if let Some(position) = &meeting.location.position {
+                self.tx.execute(
+                    "INSERT INTO meetings_rtree VALUES(?, ?, ?, ?, ?)",
+                    params![
+                        rowid,
+                        position.latitude,
+                        position.latitude,
+                        position.longitude,
+                        position.longitude
+                    ],
+                )?;
+            }
+
+            self.tx.execute(
+                "DELETE FROM meeting_types WHERE meeting = ?",
+                params![meeting.id],
+            )?;
+
+            for meeting_type in &meeting.types {
+                self.tx.execute(
+                    "INSERT INTO meeting_types(meeting, type) VALUES(?, ?)",
+                    params![meeting.id, meeting_type.to_string()],
+                )?;
+            }
+
+            self.tx.execute(
+                "DELETE FROM meetings_fts WHERE id = ?",
+                params![meeting.id],
+            )?;
+
+            self.tx.execute(
+                "INSERT INTO meetings_fts(id, name, notes, location_name, location_notes, address, region)
+                VALUES(?, ?, ?, ?, ?, ?, ?)",
+                params![
+                    meeting.id,
+                    meeting.name,
+                    meeting.notes,
+                    meeting.location.name,
+                    meeting.location.notes,
+                    meeting.location.address,
+                    meeting.location.region
+                ],
+            )?;
+
             meeting_count += 1;
         }
 
         self.total_meetings
             .fetch_add(meeting_count, Ordering::Relaxed);
 
+        Ok(report)
+    }
+
+    /// Start a savepoint, so the changes made for one source can be undone without those of
+    /// the others.
+    pub async fn savepoint(&self) -> Result<(), IndexError> {
+        self.tx.execute_batch("SAVEPOINT source")?;
         Ok(())
     }
 
-    pub async fn remove_old_meetings(&self) -> Result<(), IndexError> {
-        self.tx.execute("DELETE FROM meetings", params![])?;
+    /// Undo the changes since the savepoint and end it.
+    pub async fn rollback_to_savepoint(&self) -> Result<(), IndexError> {
+        self.tx
+            .execute_batch("ROLLBACK TO SAVEPOINT source; RELEASE SAVEPOINT source")?;
         Ok(())
     }
 
+    /// Keep the changes since the savepoint and end it.
+    pub async fn release_savepoint(&self) -> Result<(), IndexError> {
+        self.tx.execute_batch("RELEASE SAVEPOINT source")?;
+        Ok(())
+    }
+
+    fn previous_meeting(&self, id: &str) -> Result<Option<Meeting>, IndexError> {
+        let mut stmt = self.tx.prepare_cached(&format!(
+            "SELECT {MEETING_COLUMNS} FROM meetings WHERE id = ? AND removed_at IS NULL"
+        ))?;
+
+        let mut rows = stmt.query_map(params![id], meeting_from_row)?;
+
+        Ok(rows.next().transpose()?)
+    }
+
+    /// The number of meetings of `source` in the index before this import added any.
+    pub async fn previous_count(&self, source: &str) -> Result<usize, IndexError> {
+        let count = self.tx.query_row(
+            "SELECT COUNT(*) FROM meetings
+            WHERE source_key = ? AND last_seen < ? AND removed_at IS NULL",
+            params![source, self.started_at],
+            |row| row.get(0),
+        )?;
+
+        Ok(count)
+    }
+
+    /// Mark the meetings with the given source key that were not added during this import as
+    /// removed, returning their ids.
+    fn remove_meetings(&self, source_key: Option<&str>) -> Result<Vec<String>, IndexError> {
+        let ids = self
+            .tx
+            .prepare(
+                "SELECT id FROM meetings
+                WHERE source_key IS ? AND last_seen < ? AND removed_at IS NULL
+                ORDER BY id",
+            )?
+            .query_map(params![source_key, self.started_at], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+
+        for id in &ids {
+            self.tx.execute(
+                "UPDATE meetings SET removed_at = ? WHERE id = ?",
+                params![self.started_at, id],
+            )?;
+        }
+
+        Ok(ids)
+    }
+
+    /// Mark the meetings of `source` that were not added during this import as removed,
+    /// returning their ids.
+    pub async fn remove_missing_meetings(&self, source: &str) -> Result<Vec<String>, IndexError> {
+        self.remove_meetings(Some(source))
+    }
+
+    /// Keep the meetings of `source` and its sub-sources, but mark them as stale because the
+    /// source could not be fetched or was held back. Returns how many meetings were kept.
+    pub async fn mark_stale(&self, source: &str) -> Result<usize, IndexError> {
+        let stale = self.tx.execute(
+            "UPDATE meetings SET stale_since = COALESCE(stale_since, ?1)
+            WHERE (source_key = ?2 OR substr(source_key, 1, length(?2) + 1) = ?2 || '/')
+                AND last_seen < ?1 AND removed_at IS NULL",
+            params![self.started_at, source],
+        )?;
+
+        Ok(stale)
+    }
+
+    /// Mark the meetings of sources that did not report at all during this import as removed,
+    /// like sources that no longer exist. Only the meetings of `selected` sources are
+    /// considered, unless it is empty, and the meetings of `unfetched` sources, which still exist
+    /// but were not asked to report, are kept. Returns the ids of the removed meetings per source
+    /// key, which is `None` for meetings from before sources were tracked.
+    pub async fn remove_unreported_sources(
+        &self,
+        reported: &[String],
+        selected: &[String],
+        unfetched: &[String],
+    ) -> Result<Vec<(Option<String>, Vec<String>)>, IndexError> {
+        let source_keys = self
+            .tx
+            .prepare("SELECT DISTINCT source_key FROM meetings WHERE removed_at IS NULL")?
+            .query_map([], |row| row.get::<_, Option<String>>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut removed = Vec::new();
+
+        for source_key in source_keys {
+            let is_reported = source_key.as_ref().is_some_and(|key| {
+                reported
+                    .iter()
+                    .chain(unfetched)
+                    .any(|source| belongs_to_source(key, source))
+            });
+
+            let is_selected = selected.is_empty()
+                || source_key.as_ref().is_some_and(|key| {
+                    selected.iter().any(|source| belongs_to_source(key, source))
+                });
+
+            if is_selected && !is_reported {
+                let ids = self.remove_meetings(source_key.as_deref())?;
+                removed.push((source_key, ids));
+            }
+        }
+
+        Ok(removed)
+    }
+
     pub async fn commit(self) -> Result<(), IndexError> {
         self.tx.commit()?;
         Ok(())
     }
 
+    pub async fn rollback(self) -> Result<(), IndexError> {
+        self.tx.rollback()?;
+        Ok(())
+    }
+
+    pub fn started_at(&self) -> DateTime<Utc> {
+        self.started_at
+    }
+
     pub fn meetings_added(&self) -> usize {
         self.total_meetings.load(Ordering::Relaxed)
     }
@@ -116,10 +768,13 @@ pub struct MeetingIndex {
 
 impl Clone for MeetingIndex {
     fn clone(&self) -> Self {
+        let conn =
+            Connection::open_with_flags(&self.path, OpenFlags::SQLITE_OPEN_READ_WRITE).unwrap();
+        register_functions(&conn).unwrap();
+
         Self {
             path: self.path.clone(),
-            conn: Connection::open_with_flags(&self.path, OpenFlags::SQLITE_OPEN_READ_WRITE)
-                .unwrap(),
+            conn,
         }
     }
 }
@@ -131,7 +786,15 @@ impl MeetingIndex {
             OpenFlags::SQLITE_OPEN_CREATE | OpenFlags::SQLITE_OPEN_READ_WRITE,
         )?;
 
-        Self::migrate(&mut conn)?;
+        if drop_outdated_layout(&conn)? {
+            eprintln!(
+                "Removed the meetings of {}, they were stored in a layout from before ids. Run a sync to add them again.",
+                path.display()
+            );
+        }
+
+        migrate(&mut conn, MIGRATIONS)?;
+        register_functions(&conn)?;
 
         Ok(Self {
             conn,
@@ -139,128 +802,654 @@ impl MeetingIndex {
         })
     }
 
-    fn migrate(conn: &mut Connection) -> Result<(), IndexError> {
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS meetings (
-            name TEXT NOT NULL,
-            org TEXT NOT NULL,
-            notes TEXT NULL,
-            source TEXT NOT NULL,
-            updated_at DATETIME NOT NULL,
-
-            country TEXT NULL,
-            region TEXT NULL,
-            location_name TEXT NULL,
-            location_notes TEXT NULL,
-            latitude REAL NULL,
-            longitude REAL NULL,
-            address TEXT NULL,
-
-            online INTEGER NOT NULL,
-            online_url TEXT NULL,
-            online_notes TEXT NULL,
-
-            phone TEXT NULL,
-            email TEXT NULL,
-
-            duration INTEGER NULL,
-            day INTEGER NULL,
-            hour INTEGER NULL,
-            minute INTEGER NULL
-        )",
-            params![],
-        )?;
+    pub async fn search(&self, opts: &SearchOptions) -> Result<SearchResults, IndexError> {
+        let mut filter = SqlFilter::default();
+        filter.condition(String::from("removed_at IS NULL"));
 
-        Ok(())
-    }
+        let distance_column = if let Some(distance) = &opts.distance {
+            filter.named(":lat", distance.latitude);
+            filter.named(":long", distance.longitude);
+            let max_distance = filter.param(distance.distance);
+            filter.condition(format!("distance < {max_distance}"));
 
-    pub async fn search(&self, opts: &SearchOptions) -> Result<Vec<SearchMeeting>, IndexError> {
-        let mut query = String::from("SELECT ");
+            // Only compute the distance for meetings that can be close enough
+            filter.within(&distance.bounding_box());
 
-        let mut params: Vec<(&str, &dyn ToSql)> = Vec::new();
-
-        if opts.distance.is_some() {
-            // from https://stackoverflow.com/questions/27928/calculate-distance-between-two-latitude-longitude-points-haversine-formula
-            query.push_str(
-                "(
-    12742 * ASIN(SQRT(0.5 - COS((latitude - :lat) * 0.017453292519943295) / 2.0
-    +  COS(:lat * 0.017453292519943295) * COS(latitude * 0.017453292519943295)
-    * (1.0 - COS((longitude - :long) * 0.017453292519943295)) / 2.0))
-) as distance, ",
-            )
+            "haversine(:lat, :long, latitude, longitude) as distance"
         } else {
-            query.push_str("NULL as distance, ");
+            "NULL as distance"
+        };
+
+        if let Some(bounds) = &opts.bounds {
+            filter.within(bounds);
         }
 
-        query.push_str("* FROM MEETINGS");
+        for meeting_type in &opts.types {
+            let param = filter.param(meeting_type.to_string());
+            filter.condition(format!(
+                "EXISTS (SELECT 1 FROM meeting_types WHERE meeting = meetings.id AND type = {param})"
+            ));
+        }
 
-        if opts.distance.is_some() {
-            query.push_str("\nWHERE distance < :distance\nORDER BY distance")
+        filter.any_of("attendance", opts.attendance.iter().map(|mode| mode.to_string()));
+        filter.any_of("org", opts.orgs.iter().map(|org| org.to_string()));
+        filter.any_of("day", opts.days.iter().map(|day| day.to_day_index()));
+
+        let start_after = opts.start_after.map(|time| filter.param(minute_of_day(time)));
+        let start_before = opts.start_before.map(|time| filter.param(minute_of_day(time)));
+
+        match (start_after, start_before) {
+            (Some(after), Some(before)) if opts.start_after > opts.start_before => {
+                // The window wraps around midnight
+                filter.condition(format!(
+                    "(hour * 60 + minute >= {after} OR hour * 60 + minute <= {before})"
+                ));
+            }
+            (after, before) => {
+                if let Some(after) = after {
+                    filter.condition(format!("hour * 60 + minute >= {after}"));
+                }
+
+                if let Some(before) = before {
+                    filter.condition(format!("hour * 60 + minute <= {before}"));
+                }
+            }
         }
 
-        if let Some(distance) = &opts.distance {
-            params.push((":lat", &distance.latitude));
-            params.push((":long", &distance.longitude));
-            params.push((":distance", &distance.distance));
+        if let Some(country) = &opts.country {
+            let param = filter.param(country.clone());
+            filter.condition(format!("country = {param} COLLATE NOCASE"));
         }
 
-        let mut stmt = self.conn.prepare(query.as_str())?;
+        if let Some(region) = &opts.region {
+            let param = filter.param(region.clone());
+            filter.condition(format!("region = {param} COLLATE NOCASE"));
+        }
 
-        let rows = stmt.query_map(params.as_slice(), |row| {
-            let position = match (row.get("latitude")?, row.get("longitude")?) {
-                (Some(latitude), Some(longitude)) => Some(Position {
-                    latitude,
-                    longitude,
-                }),
-                _ => None,
-            };
+        let mut from = String::from(" FROM meetings");
+
+        if let Some(text) = &opts.text {
+            let param = filter.param(fts_query(text));
+            from.push_str(&format!(
+                " JOIN (SELECT id AS match_id, rank AS match_rank FROM meetings_fts WHERE meetings_fts MATCH {param}) ON match_id = meetings.id"
+            ));
+        }
 
-            // TODO: handle parse errors
+        from.push_str(&filter.where_clause());
+        let params = filter.params();
+
+        let total = self.conn.query_row(
+            &format!("SELECT COUNT(*) FROM (SELECT {distance_column}{from})"),
+            params.as_slice(),
+            |row| row.get(0),
+        )?;
+
+        let mut query = format!("SELECT {distance_column}, {MEETING_COLUMNS}{from}");
+
+        let mut order = Vec::new();
+
+        if opts.text.is_some() {
+            order.push("match_rank");
+        }
 
+        if opts.distance.is_some() {
+            order.push("distance");
+        }
+
+        // Always order by something unique, so pages do not overlap.
+        order.push("meetings.id");
+
+        query.push_str("\nORDER BY ");
+        query.push_str(&order.join(", "));
+
+        // A negative limit means no limit in SQLite.
+        query.push_str("\nLIMIT :limit OFFSET :offset");
+        let limit = opts.limit.map(|limit| limit as i64).unwrap_or(-1);
+        let offset = opts.offset as i64;
+
+        let mut page_params = params;
+        page_params.push((":limit", &limit));
+        page_params.push((":offset", &offset));
+
+        let mut stmt = self.conn.prepare(query.as_str())?;
+
+        let rows = stmt.query_map(page_params.as_slice(), |row| {
             Ok(SearchMeeting {
                 distance: row.get("distance")?,
-                meeting: Meeting {
-                    name: row.get("name")?,
-                    org: row.get::<_, String>("org")?.parse().unwrap(),
-                    notes: row.get("notes")?,
-                    source: row.get("source")?,
-                    updated_at: row.get("updated_at")?,
-                    contact: Contact {
-                        email: row.get("email")?,
-                        phone: row.get("phone")?,
-                    },
-                    location: Location {
-                        position,
-                        name: row.get("location_name")?,
-                        notes: row.get("location_notes")?,
-                        country: row.get("country")?,
-                        region: row.get("region")?,
-                        address: row.get("address")?,
-                    },
-                    online_options: OnlineOptions {
-                        url: row.get("online_url")?,
-                        notes: row.get("online_notes")?,
-                        is_online: row.get("online")?,
-                    },
-                    time: MeetingTime::Recurring {
-                        day: WeekDay::from_day_index(row.get("day")?),
-                        hour: row.get("hour")?,
-                        minute: row.get("minute")?,
-                    },
-                    duration: row
-                        .get::<_, Option<u64>>("duration")?
-                        .map(|secs| Duration::from_secs(secs)),
-                },
+                first_seen: row.get("first_seen")?,
+                last_seen: row.get("last_seen")?,
+                stale_since: row.get("stale_since")?,
+                meeting: meeting_from_row(row)?,
             })
         })?;
 
-        Ok(rows.filter_map(|r| r.ok()).collect())
+        Ok(SearchResults {
+            total,
+            offset: opts.offset,
+            limit: opts.limit,
+            meetings: rows.filter_map(|r| r.ok()).collect(),
+        })
+    }
+
+    pub async fn get(&self, id: &str) -> Result<Option<Meeting>, IndexError> {
+        let mut stmt = self
+            .conn
+            .prepare(&format!(
+                "SELECT {MEETING_COLUMNS} FROM meetings WHERE id = ? AND removed_at IS NULL"
+            ))?;
+
+        let mut rows = stmt.query_map(params![id], meeting_from_row)?;
+
+        Ok(rows.next().transpose()?)
     }
 
     pub async fn start_import(&mut self) -> Result<MeetingImport<'_>, IndexError> {
         Ok(MeetingImport {
             tx: self.conn.transaction()?,
+            started_at: Utc::now(),
             total_meetings: Default::default(),
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    /// Add the meetings of each source in a committed import.
+    async fn sync(index: &mut MeetingIndex, sources: &[(&str, Vec<Meeting>)]) {
+        let import = index.start_import().await.unwrap();
+
+        for (source, meetings) in sources {
+            import.add_meetings(source, meetings.iter()).await.unwrap();
+        }
+
+        import.commit().await.unwrap();
+    }
+
+    fn stale_ids(conn: &Connection) -> Vec<String> {
+        conn.prepare("SELECT id FROM meetings WHERE stale_since IS NOT NULL ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn count(conn: &Connection, table: &str) -> usize {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+            row.get(0)
+        })
+        .unwrap()
+    }
+
+    fn column<T: rusqlite::types::FromSql>(conn: &Connection, id: &str, column: &str) -> T {
+        conn.query_row(
+            &format!("SELECT {column} FROM meetings WHERE id = ?"),
+            params![id],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    fn types_of(conn: &Connection, id: &str) -> Vec<String> {
+        conn.prepare("SELECT type FROM meeting_types WHERE meeting = ? ORDER BY type")
+            .unwrap()
+            .query_map(params![id], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn resyncing_keeps_first_seen_and_moves_last_seen() {
+        let mut index = MeetingIndex::open(Path::new(":memory:")).unwrap();
+        let original = Meeting::example("a:1");
+        sync(&mut index, &[("a", vec![original.clone()])]).await;
+
+        let first_seen: DateTime<Utc> = column(&index.conn, "a:1", "first_seen");
+        let last_seen: DateTime<Utc> = column(&index.conn, "a:1", "last_seen");
+        assert_eq!(first_seen, last_seen);
+
+        // The source reports a new updated_at, but nothing about the meeting changed
+        let mut unchanged = original.clone();
+        unchanged.updated_at = Utc::now();
+        unchanged.types = vec![MeetingType::Open];
+
+        let import = index.start_import().await.unwrap();
+        let report = import.add_meetings("a", [unchanged].iter()).await.unwrap();
+        import.commit().await.unwrap();
+
+        assert!(report.added.is_empty());
+        assert!(report.modified.is_empty());
+        assert_eq!(
+            column::<DateTime<Utc>>(&index.conn, "a:1", "first_seen"),
+            first_seen
+        );
+        assert!(column::<DateTime<Utc>>(&index.conn, "a:1", "last_seen") > last_seen);
+        assert_eq!(
+            index.get("a:1").await.unwrap().unwrap().updated_at,
+            original.updated_at
+        );
+    }
+
+    #[tokio::test]
+    async fn resyncing_a_changed_meeting_rewrites_its_rows() {
+        let mut index = MeetingIndex::open(Path::new(":memory:")).unwrap();
+        sync(&mut index, &[("a", vec![Meeting::example("a:1")])]).await;
+
+        let mut changed = Meeting::example("a:1");
+        changed.name = String::from("Renamed");
+        changed.location.position = Some(Position::new(53.0, 6.0));
+        changed.types = vec![MeetingType::Closed, MeetingType::Speaker];
+
+        let import = index.start_import().await.unwrap();
+        let report = import
+            .add_meetings("a", [changed.clone()].iter())
+            .await
+            .unwrap();
+        import.commit().await.unwrap();
+
+        assert!(report.added.is_empty());
+        assert_eq!(report.modified.len(), 1);
+        assert_eq!(report.modified[0].id, "a:1");
+
+        let stored = index.get("a:1").await.unwrap().unwrap();
+        assert_eq!(stored.name, "Renamed");
+        assert_eq!(stored.updated_at, changed.updated_at);
+
+        let rowid: i64 = column(&index.conn, "a:1", "rowid");
+        let (latitude, longitude): (f64, f64) = index
+            .conn
+            .query_row(
+                "SELECT min_latitude, min_longitude FROM meetings_rtree WHERE id = ?",
+                params![rowid],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_close(latitude, 53.0);
+        assert_close(longitude, 6.0);
+        assert_eq!(count(&index.conn, "meetings_rtree"), 1);
+
+        assert_eq!(types_of(&index.conn, "a:1"), ["Closed", "Speaker"]);
+
+        let names: Vec<String> = index
+            .conn
+            .prepare("SELECT name FROM meetings_fts WHERE id = ?")
+            .unwrap()
+            .query_map(params!["a:1"], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(names, ["Renamed"]);
+    }
+
+    #[tokio::test]
+    async fn a_removed_meeting_can_return() {
+        let mut index = MeetingIndex::open(Path::new(":memory:")).unwrap();
+        sync(
+            &mut index,
+            &[("a", vec![Meeting::example("a:1"), Meeting::example("a:2")])],
+        )
+        .await;
+        let first_seen: DateTime<Utc> = column(&index.conn, "a:2", "first_seen");
+
+        let import = index.start_import().await.unwrap();
+        import
+            .add_meetings("a", [Meeting::example("a:1")].iter())
+            .await
+            .unwrap();
+        import.remove_missing_meetings("a").await.unwrap();
+        import.commit().await.unwrap();
+
+        assert!(column::<Option<DateTime<Utc>>>(&index.conn, "a:2", "removed_at").is_some());
+        assert!(index.get("a:2").await.unwrap().is_none());
+
+        let import = index.start_import().await.unwrap();
+        let report = import
+            .add_meetings(
+                "a",
+                [Meeting::example("a:1"), Meeting::example("a:2")].iter(),
+            )
+            .await
+            .unwrap();
+        import.commit().await.unwrap();
+
+        assert_eq!(report.added, ["a:2"]);
+        assert!(column::<Option<DateTime<Utc>>>(&index.conn, "a:2", "removed_at").is_none());
+        assert_eq!(
+            column::<DateTime<Utc>>(&index.conn, "a:2", "first_seen"),
+            first_seen
+        );
+        assert!(index.get("a:2").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn stores_every_kind_of_meeting_time() {
+        let mut index = MeetingIndex::open(Path::new(":memory:")).unwrap();
+        let date = |day| NaiveDate::from_ymd_opt(2024, 3, day).unwrap();
+
+        let times = [
+            MeetingTime::Recurring {
+                day: WeekDay::Sunday,
+                hour: 9,
+                minute: 30,
+            },
+            MeetingTime::Monthly {
+                week: 1,
+                day: WeekDay::Tuesday,
+                hour: 19,
+                minute: 0,
+            },
+            MeetingTime::Monthly {
+                week: -1,
+                day: WeekDay::Friday,
+                hour: 20,
+                minute: 15,
+            },
+            MeetingTime::Biweekly {
+                anchor: date(6),
+                hour: 18,
+                minute: 45,
+            },
+            MeetingTime::Once {
+                date: date(16),
+                hour: 10,
+                minute: 0,
+            },
+        ];
+
+        let meetings: Vec<Meeting> = times
+            .iter()
+            .enumerate()
+            .map(|(i, time)| Meeting {
+                time: time.clone(),
+                ..Meeting::example(&format!("a:{i}"))
+            })
+            .collect();
+        sync(&mut index, &[("a", meetings)]).await;
+
+        for (i, time) in times.iter().enumerate() {
+            let stored = index.get(&format!("a:{i}")).await.unwrap().unwrap();
+            assert_eq!(&stored.time, time);
+        }
+
+        // The day of a dated meeting is the weekday of its date
+        let results = index
+            .search(&SearchOptions {
+                days: vec![WeekDay::Wednesday],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let ids: Vec<&str> = results
+            .meetings
+            .iter()
+            .map(|result| result.meeting.id.as_str())
+            .collect();
+        assert_eq!(ids, ["a:3"]);
+    }
+
+    #[tokio::test]
+    async fn marking_a_failed_source_stale_keeps_its_meetings() {
+        let mut index = MeetingIndex::open(Path::new(":memory:")).unwrap();
+        sync(
+            &mut index,
+            &[
+                ("a", vec![Meeting::example("a:1"), Meeting::example("a:2")]),
+                ("b", vec![Meeting::example("b:1")]),
+            ],
+        )
+        .await;
+
+        let import = index.start_import().await.unwrap();
+        import
+            .add_meetings("b", [Meeting::example("b:1")].iter())
+            .await
+            .unwrap();
+        assert_eq!(import.mark_stale("a").await.unwrap(), 2);
+        import.commit().await.unwrap();
+
+        assert!(index.get("a:1").await.unwrap().is_some());
+        assert!(index.get("a:2").await.unwrap().is_some());
+        assert_eq!(stale_ids(&index.conn), ["a:1", "a:2"]);
+
+        // The next successful sync clears the mark
+        sync(
+            &mut index,
+            &[("a", vec![Meeting::example("a:1"), Meeting::example("a:2")])],
+        )
+        .await;
+        assert!(stale_ids(&index.conn).is_empty());
+    }
+
+    #[tokio::test]
+    async fn marking_a_source_stale_covers_its_sub_sources() {
+        let mut index = MeetingIndex::open(Path::new(":memory:")).unwrap();
+        sync(
+            &mut index,
+            &[
+                ("bmlt/one.org", vec![Meeting::example("one:1")]),
+                ("bmlt/two.org", vec![Meeting::example("two:1")]),
+                ("bmltx", vec![Meeting::example("x:1")]),
+            ],
+        )
+        .await;
+
+        let import = index.start_import().await.unwrap();
+        assert_eq!(import.mark_stale("bmlt").await.unwrap(), 2);
+        import.commit().await.unwrap();
+
+        assert_eq!(stale_ids(&index.conn), ["one:1", "two:1"]);
+    }
+
+    #[tokio::test]
+    async fn rolling_back_to_the_savepoint_discards_a_half_added_source() {
+        let mut index = MeetingIndex::open(Path::new(":memory:")).unwrap();
+        sync(&mut index, &[("a", vec![Meeting::example("a:1")])]).await;
+
+        // A duration that does not fit in the database fails after the first meeting was added
+        let mut broken = Meeting::example("b:2");
+        broken.duration = Some(Duration::from_secs(u64::MAX));
+
+        let import = index.start_import().await.unwrap();
+        import.savepoint().await.unwrap();
+        assert!(import
+            .add_meetings("b", [Meeting::example("b:1"), broken].iter())
+            .await
+            .is_err());
+        import.rollback_to_savepoint().await.unwrap();
+
+        import.savepoint().await.unwrap();
+        import
+            .add_meetings("c", [Meeting::example("c:1")].iter())
+            .await
+            .unwrap();
+        import.release_savepoint().await.unwrap();
+        import.commit().await.unwrap();
+
+        assert!(index.get("b:1").await.unwrap().is_none());
+        assert!(index.get("a:1").await.unwrap().is_some());
+        assert!(index.get("c:1").await.unwrap().is_some());
+        assert_eq!(count(&index.conn, "meetings"), 2);
+        assert_eq!(count(&index.conn, "meetings_rtree"), 2);
+        assert_eq!(count(&index.conn, "meeting_types"), 2);
+        assert_eq!(count(&index.conn, "meetings_fts"), 2);
+    }
+
+    #[tokio::test]
+    async fn removes_the_meetings_a_source_no_longer_reports() {
+        let mut index = MeetingIndex::open(Path::new(":memory:")).unwrap();
+        sync(
+            &mut index,
+            &[
+                ("a", vec![Meeting::example("a:1"), Meeting::example("a:2")]),
+                ("b", vec![Meeting::example("b:1")]),
+            ],
+        )
+        .await;
+
+        let import = index.start_import().await.unwrap();
+        import
+            .add_meetings("a", [Meeting::example("a:1")].iter())
+            .await
+            .unwrap();
+        assert_eq!(import.remove_missing_meetings("a").await.unwrap(), ["a:2"]);
+        import.commit().await.unwrap();
+
+        assert!(index.get("a:1").await.unwrap().is_some());
+        assert!(index.get("a:2").await.unwrap().is_none());
+        // Other sources are left alone
+        assert!(index.get("b:1").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn removes_the_meetings_of_unreported_sources() {
+        let mut index = MeetingIndex::open(Path::new(":memory:")).unwrap();
+        sync(
+            &mut index,
+            &[
+                ("a", vec![Meeting::example("a:1")]),
+                ("b", vec![Meeting::example("b:1")]),
+                ("bmlt/one.org", vec![Meeting::example("one:1")]),
+                ("disabled", vec![Meeting::example("disabled:1")]),
+            ],
+        )
+        .await;
+
+        let import = index.start_import().await.unwrap();
+        let reported = [String::from("a")];
+        let unfetched = [String::from("disabled")];
+
+        // Only the selected sources are considered
+        let removed = import
+            .remove_unreported_sources(&reported, &[String::from("bmlt")], &unfetched)
+            .await
+            .unwrap();
+        assert_eq!(
+            removed,
+            [(
+                Some(String::from("bmlt/one.org")),
+                vec![String::from("one:1")]
+            )]
+        );
+
+        let removed = import
+            .remove_unreported_sources(&reported, &[], &unfetched)
+            .await
+            .unwrap();
+        assert_eq!(
+            removed,
+            [(Some(String::from("b")), vec![String::from("b:1")])]
+        );
+        import.commit().await.unwrap();
+
+        assert!(index.get("a:1").await.unwrap().is_some());
+        assert!(index.get("disabled:1").await.unwrap().is_some());
+        assert!(index.get("b:1").await.unwrap().is_none());
+        assert!(index.get("one:1").await.unwrap().is_none());
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 0.001,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn fts_query_matches_every_word_as_a_prefix() {
+        assert_eq!(fts_query("  open  meet "), r#""open"* "meet"*"#);
+        assert_eq!(fts_query(""), "");
+    }
+
+    #[test]
+    fn fts_query_escapes_fts_syntax() {
+        assert_eq!(fts_query(r#"say "hi""#), r#""say"* """hi"""*"#);
+        assert_eq!(fts_query("a OR b*"), r#""a"* "OR"* "b*"*"#);
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE VIRTUAL TABLE meetings_fts USING fts5(name);
+            INSERT INTO meetings_fts VALUES ('Step \"12\" (NEAR) AND more');",
+        )
+        .unwrap();
+
+        // Operators are searched as words and column filters as a phrase
+        let cases = [
+            (r#"step "12"#, 1),
+            ("NEAR(", 1),
+            ("AND", 1),
+            ("more -", 1),
+            ("^step", 1),
+            ("name:step", 0),
+        ];
+
+        for (text, expected) in cases {
+            let count: usize = conn
+                .query_row(
+                    "SELECT count(*) FROM meetings_fts WHERE meetings_fts MATCH ?",
+                    params![fts_query(text)],
+                    |row| row.get(0),
+                )
+                .unwrap_or_else(|e| panic!("{text:?} failed: {e}"));
+
+            assert_eq!(count, expected, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn bounding_box_around_a_position() {
+        let search = DistanceSearch {
+            latitude: 52.0,
+            longitude: 5.0,
+            distance: 10.0,
+        };
+
+        let bounds = search.bounding_box();
+
+        assert_close(bounds.south, 51.910);
+        assert_close(bounds.north, 52.090);
+        assert_close(bounds.west, 4.854);
+        assert_close(bounds.east, 5.146);
+
+        // The edges are at least the distance away from the center
+        let center = Position::new(52.0, 5.0);
+        assert!(haversine(&center, &Position::new(bounds.north, 5.0)) >= 9.999);
+        assert!(haversine(&center, &Position::new(52.0, bounds.east)) >= 9.999);
+    }
+
+    #[test]
+    fn bounding_box_wraps_around_the_antimeridian() {
+        let search = DistanceSearch {
+            latitude: 0.0,
+            longitude: 179.9,
+            distance: 50.0,
+        };
+
+        let bounds = search.bounding_box();
+
+        assert!(bounds.west > bounds.east);
+        assert_close(bounds.west, 179.450);
+        assert_close(bounds.east, -179.650);
+        assert_close(bounds.south, -0.450);
+        assert_close(bounds.north, 0.450);
+    }
+
+    #[test]
+    fn bounding_box_reaching_a_pole_contains_every_longitude() {
+        let search = DistanceSearch {
+            latitude: 89.9,
+            longitude: 10.0,
+            distance: 50.0,
+        };
+
+        let bounds = search.bounding_box();
+
+        assert_close(bounds.south, 89.450);
+        assert_close(bounds.north, 90.0);
+        assert_close(bounds.west, -180.0);
+        assert_close(bounds.east, 180.0);
+    }
+}
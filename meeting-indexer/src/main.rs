@@ -6,23 +6,72 @@ use std::path::PathBuf;
 
 use crate::server::start_server;
 use crate::source::FetchMeeting;
-use clap::{Parser, Subcommand};
-use source::FetchMeetingResult;
+use chrono::NaiveDate;
+use clap::{ArgGroup, Args, Parser, Subcommand};
+use geocoder::boundaries::{ReverseGeocoder, TimeZoneLocator};
+use geocoder::offline::{GazetteerFormat, OfflineGeocoder};
+use geocoder::GeocodeRules;
+use position_lookup::{CacheFilter, CachedPosition};
+use source::{FetchedMeetings, SourceRegistry, SourceResult};
+use sync_report::{FieldFailures, RejectedPosition, SourceStatus, SyncReport};
 use tokio::{
     join,
     sync::mpsc::{channel, Receiver},
 };
 
+pub mod config;
+pub mod geocoder;
 pub mod index;
 pub mod meeting;
+pub mod migration;
 pub mod position_lookup;
 pub mod server;
 pub mod source;
+pub mod sync_report;
 
 #[derive(Subcommand)]
 enum Commands {
     /// Synchronize the database
-    Sync,
+    Sync {
+        /// Hold back a source when it has more than this percentage fewer meetings than in the
+        /// previous sync
+        #[arg(long, value_name = "PERCENT", default_value_t = 50.0)]
+        max_drop: f64,
+
+        /// Do not commit anything when a source is held back
+        #[arg(long)]
+        abort_on_drop: bool,
+
+        /// Write the changes per source to sync-report.json in the data directory
+        #[arg(long)]
+        report: bool,
+
+        /// Build the staging and report what would change, without committing it
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Only sync the source with this name, can be given multiple times
+        #[arg(long = "source", value_name = "NAME")]
+        sources: Vec<String>,
+    },
+
+    /// Inspect the sources of meetings
+    Sources {
+        #[command(subcommand)]
+        command: SourcesCommands,
+    },
+
+    /// Manage the gazetteer of the offline geocoder
+    Gazetteer {
+        #[command(subcommand)]
+        command: GazetteerCommands,
+    },
+
+    /// Manage the cache of geocoded positions
+    Geocode {
+        #[command(subcommand)]
+        command: GeocodeCommands,
+    },
 
     /// Launch a webserver
     Serve {
@@ -34,6 +83,107 @@ enum Commands {
     },
 }
 
+#[derive(Subcommand)]
+enum SourcesCommands {
+    /// List every source that can be synced
+    List,
+}
+
+#[derive(Subcommand)]
+enum GazetteerCommands {
+    /// Load a dump, replacing the previous dump with the same name
+    Load {
+        /// openaddresses (CSV) or geonames-postal (GeoNames postal codes)
+        #[arg(short, long)]
+        format: GazetteerFormat,
+
+        /// The name of the dump, the file name by default
+        #[arg(short, long)]
+        name: Option<String>,
+
+        file: PathBuf,
+    },
+}
+
+/// Selects entries of the geocode cache, every entry by default.
+#[derive(Args)]
+struct CacheFilterArgs {
+    /// Only queries containing this text
+    #[arg(long, value_name = "TEXT")]
+    matching: Option<String>,
+
+    /// Only queries that did not find a position
+    #[arg(long)]
+    null: bool,
+
+    /// Only queries requested before this date
+    #[arg(long, value_name = "YYYY-MM-DD")]
+    before: Option<NaiveDate>,
+}
+
+impl From<CacheFilterArgs> for CacheFilter {
+    fn from(args: CacheFilterArgs) -> Self {
+        Self {
+            matching: args.matching,
+            null: args.null,
+            before: args.before,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum GeocodeCommands {
+    /// List the cached queries and their positions
+    List {
+        #[command(flatten)]
+        filter: CacheFilterArgs,
+    },
+
+    /// List the cached queries containing a text
+    Search { text: String },
+
+    /// Remove cached queries that are older than a date, or the ones that did not find a position
+    #[command(group(
+        ArgGroup::new("selection")
+            .required(true)
+            .args(["before", "null"])
+    ))]
+    Purge {
+        /// Remove the queries requested before this date
+        #[arg(long, value_name = "YYYY-MM-DD")]
+        before: Option<NaiveDate>,
+
+        /// Remove the queries that did not find a position
+        #[arg(long)]
+        null: bool,
+    },
+
+    /// Ask the geocoder again for the given queries or the selected cached queries
+    #[command(group(
+        ArgGroup::new("selection")
+            .required(true)
+            .multiple(true)
+            .args(["queries", "matching", "null", "before"])
+    ))]
+    Resolve {
+        queries: Vec<String>,
+
+        #[command(flatten)]
+        filter: CacheFilterArgs,
+    },
+
+    /// Write the cache as CSV
+    Export {
+        file: PathBuf,
+
+        #[command(flatten)]
+        filter: CacheFilterArgs,
+    },
+
+    /// Add the queries of a CSV export to the cache, replacing the cached ones
+    Import { file: PathBuf },
+}
+
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
@@ -51,16 +201,121 @@ async fn main() -> Result<(), Box<dyn Error>> {
     let data_path = PathBuf::from(cli.data_dir);
     let meeting_db_path = data_path.join("meetings.db");
 
-    let mut index = index::MeetingIndex::open(&meeting_db_path)?;
-
     match cli.command {
-        Commands::Sync => {
+        Commands::Sync {
+            max_drop,
+            abort_on_drop,
+            report,
+            dry_run,
+            sources,
+        } => {
+            let config = config::Config::load(&data_path)?;
+            let registry = SourceRegistry::from_config(&config)?;
+
+            for name in &sources {
+                if !registry.contains(name) {
+                    return Err(format!("Unknown source {name}, see `sources list`").into());
+                }
+            }
+
+            let mut index = index::MeetingIndex::open(&meeting_db_path)?;
+
             let position_db_path = data_path.join("positions.db");
-            let position_lookup = position_lookup::PositionLookup::open(&position_db_path)?;
+            let geocoders = geocoder::from_config(&config.geocoder, &data_path)
+                .map_err(config::ConfigError::InvalidGeocoder)?;
+            let position_lookup =
+                position_lookup::PositionLookup::open(&position_db_path, geocoders)?;
+
+            if dry_run {
+                position_lookup.discard_changes()?;
+            }
 
-            sync_index(&mut index, &position_lookup).await?;
+            let reverse_geocoder = match &config.boundaries {
+                Some(boundaries) => Some(ReverseGeocoder::load(&data_path, boundaries)?),
+                None => None,
+            };
+
+            let timezone_locator = match &config.timezones {
+                Some(timezones) => Some(TimeZoneLocator::load(&data_path, timezones)?),
+                None => None,
+            };
+
+            let options = SyncOptions {
+                max_drop,
+                abort_on_drop,
+                dry_run,
+                sources,
+                geocode_rules: config.geocoder.rules.clone(),
+            };
+
+            let sync_report = sync_index(
+                &mut index,
+                &registry,
+                &position_lookup,
+                reverse_geocoder.as_ref(),
+                timezone_locator.as_ref(),
+                &options,
+            )
+            .await?;
+            println!("{sync_report}");
+
+            if report {
+                let report_path = data_path.join("sync-report.json");
+                std::fs::write(&report_path, serde_json::to_string_pretty(&sync_report)?)?;
+                println!("Wrote the changes to {}", report_path.display());
+            }
+        }
+        Commands::Sources {
+            command: SourcesCommands::List,
+        } => {
+            let registry = SourceRegistry::from_config(&config::Config::load(&data_path)?)?;
+
+            for source in registry.sources() {
+                let state = if source.enabled {
+                    "enabled"
+                } else {
+                    "disabled"
+                };
+
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    source.name(),
+                    source.source.organization(),
+                    state,
+                    source.source.url()
+                );
+            }
+        }
+        Commands::Gazetteer {
+            command: GazetteerCommands::Load { format, name, file },
+        } => {
+            let name = name.unwrap_or_else(|| {
+                file.file_name()
+                    .unwrap_or(file.as_os_str())
+                    .to_string_lossy()
+                    .to_string()
+            });
+
+            let gazetteer_path = geocoder::gazetteer_path(&data_path);
+            let gazetteer = OfflineGeocoder::open(&gazetteer_path)?;
+            let count = gazetteer.load(format, &name, std::fs::File::open(&file)?)?;
+
+            println!(
+                "Loaded {count} {format} records of {name} into {}",
+                gazetteer_path.display()
+            );
+        }
+        Commands::Geocode { command } => {
+            let config = config::Config::load(&data_path)?;
+            let geocoders = geocoder::from_config(&config.geocoder, &data_path)
+                .map_err(config::ConfigError::InvalidGeocoder)?;
+            let position_lookup =
+                position_lookup::PositionLookup::open(&data_path.join("positions.db"), geocoders)?;
+
+            run_geocode_command(command, position_lookup).await?;
         }
         Commands::Serve { port, address } => {
+            let index = index::MeetingIndex::open(&meeting_db_path)?;
             start_server(index, address, port).await?;
         }
     }
@@ -68,59 +323,404 @@ async fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+fn print_cached_position(entry: &CachedPosition) {
+    let position_text = match &entry.geocode {
+        Some(geocode) => {
+            let mut details = Vec::new();
+
+            if let Some(precision) = geocode.precision {
+                details.push(format!("{precision} precision"));
+            }
+
+            if let Some(confidence) = geocode.confidence {
+                details.push(format!("confidence {confidence}"));
+            }
+
+            if let Some(country) = geocode.country_code.as_ref().or(geocode.country.as_ref()) {
+                details.push(country.clone());
+            }
+
+            let details_text = if details.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", details.join(", "))
+            };
+
+            format!(
+                "{}, {}{details_text}",
+                geocode.position.longitude, geocode.position.latitude
+            )
+        }
+        None => String::from("NULL"),
+    };
+
+    println!(
+        "\"{}\" mapped to {position_text} on {}",
+        entry.query,
+        entry.requested_at.format("%Y-%m-%d")
+    );
+}
+
+async fn run_geocode_command(
+    command: GeocodeCommands,
+    mut position_lookup: position_lookup::PositionLookup,
+) -> Result<(), Box<dyn Error>> {
+    match command {
+        GeocodeCommands::List { filter } => {
+            let entries = position_lookup.cached_positions(&filter.into())?;
+
+            for entry in &entries {
+                print_cached_position(entry);
+            }
+
+            println!("{} cached queries", entries.len());
+        }
+        GeocodeCommands::Search { text } => {
+            let filter = CacheFilter {
+                matching: Some(text),
+                ..Default::default()
+            };
+
+            for entry in &position_lookup.cached_positions(&filter)? {
+                print_cached_position(entry);
+            }
+        }
+        GeocodeCommands::Purge { before, null } => {
+            let filter = CacheFilter {
+                matching: None,
+                null,
+                before,
+            };
+
+            let count = position_lookup.purge(&filter)?;
+            println!("Removed {count} cached queries");
+        }
+        GeocodeCommands::Resolve { queries, filter } => {
+            let queries = if queries.is_empty() {
+                position_lookup
+                    .cached_positions(&filter.into())?
+                    .into_iter()
+                    .map(|entry| entry.query)
+                    .collect()
+            } else {
+                queries
+            };
+
+            for query in &queries {
+                match position_lookup.resolve(query).await {
+                    Ok(Some(geocode)) => println!(
+                        "Mapped \"{query}\" to {}, {}",
+                        geocode.position.longitude, geocode.position.latitude
+                    ),
+                    Ok(None) => println!("Mapped \"{query}\" to NULL"),
+                    Err(e @ position_lookup::PositionLookupError::NoGeocoder) => {
+                        return Err(e.into())
+                    }
+                    Err(e) => eprintln!("Failed to map \"{query}\": {e}"),
+                }
+            }
+        }
+        GeocodeCommands::Export { file, filter } => {
+            let output = std::fs::File::create(&file)?;
+            let count = position_lookup.export_csv(&filter.into(), output)?;
+            println!("Exported {count} cached queries to {}", file.display());
+        }
+        GeocodeCommands::Import { file } => {
+            let input = std::fs::File::open(&file)?;
+            let count = position_lookup.import_csv(input)?;
+            println!("Imported {count} cached queries from {}", file.display());
+        }
+    }
+
+    Ok(())
+}
+
+/// Look up the positions of meetings without one, leaving out the results the rules reject.
 async fn lookup_meeting_positions(
-    meetings: &mut Vec<FetchMeeting>,
+    meetings: &mut [FetchMeeting],
     position_lookup: &position_lookup::PositionLookup,
-) {
+    reverse_geocoder: Option<&ReverseGeocoder>,
+    rules: &GeocodeRules,
+) -> Vec<RejectedPosition> {
+    let mut rejected = Vec::new();
+
     for meeting in meetings.iter_mut() {
-        match (&meeting.meeting.location.position, &meeting.position_query) {
-            (None, Some(query)) => {
-                let lookup_result = position_lookup.search(query.as_str()).await;
-
-                match lookup_result {
-                    Ok(lookup) => {
-                        let cache_text = if lookup.cached { " (cached)" } else { "" };
-                        let position_text = if let Some(position) = &lookup.position {
-                            format!("{}, {}", position.longitude, position.latitude)
-                        } else {
-                            String::from("NULL")
-                        };
-
-                        println!("Mapped \"{query}\" to {position_text}{cache_text}");
-
-                        meeting.meeting.location.position = lookup.position;
+        if let (None, Some(query)) = (&meeting.meeting.location.position, &meeting.position_query) {
+            let lookup_result = position_lookup.search(query.as_str()).await;
+
+            match lookup_result {
+                Ok(lookup) => {
+                    let cache_text = if lookup.cached {
+                        " (cached)"
+                    } else if lookup.offline {
+                        " (offline)"
+                    } else {
+                        ""
+                    };
+
+                    let Some(mut geocode) = lookup.geocode else {
+                        println!("Mapped \"{query}\" to NULL{cache_text}");
+                        continue;
+                    };
+
+                    let precision_text = match geocode.precision {
+                        Some(precision) => format!(" ({precision} precision)"),
+                        None => String::new(),
+                    };
+
+                    println!(
+                        "Mapped \"{query}\" to {}, {}{precision_text}{cache_text}",
+                        geocode.position.longitude, geocode.position.latitude
+                    );
+
+                    if geocode.country_code.is_none() {
+                        geocode.country_code = reverse_geocoder
+                            .and_then(|reverse_geocoder| reverse_geocoder.locate(&geocode.position))
+                            .and_then(|area| area.country.clone());
                     }
-                    Err(e) => {
-                        eprintln!("Failed to map \"{query}\": {e}");
+
+                    let declared_country = meeting.meeting.location.country.as_deref();
+
+                    if let Some(reason) = rules.check(&geocode, declared_country) {
+                        eprintln!("Rejected the position of \"{query}\": {reason}");
+                        rejected.push(RejectedPosition {
+                            id: meeting.meeting.id.clone(),
+                            query: query.clone(),
+                            reason,
+                        });
+                        continue;
                     }
+
+                    meeting.meeting.location.position = Some(geocode.position);
+                }
+                Err(e) => {
+                    eprintln!("Failed to map \"{query}\": {e}");
                 }
             }
-            _ => {}
         }
     }
+
+    rejected
 }
 
+/// Set the country and region of meetings from their position.
+fn correct_meeting_areas(
+    meetings: &mut [FetchMeeting],
+    reverse_geocoder: &ReverseGeocoder,
+    source: &str,
+) {
+    let mut corrected = 0;
+
+    for meeting in meetings.iter_mut() {
+        if reverse_geocoder.correct(&mut meeting.meeting.location) {
+            corrected += 1;
+        }
+    }
+
+    if corrected > 0 {
+        println!(
+            "Set the country or region of {corrected} meetings of {source} from their position"
+        );
+    }
+}
+
+/// Set the time zone of meetings without one from their position, and warn about the meetings
+/// that still have none.
+fn locate_meeting_timezones(
+    meetings: &mut [FetchMeeting],
+    timezone_locator: Option<&TimeZoneLocator>,
+    source: &str,
+) {
+    let mut located = 0;
+    let mut missing = 0;
+
+    for meeting in meetings.iter_mut().map(|meeting| &mut meeting.meeting) {
+        if meeting.timezone.is_some() {
+            continue;
+        }
+
+        meeting.timezone = timezone_locator
+            .zip(meeting.location.position.as_ref())
+            .and_then(|(timezone_locator, position)| timezone_locator.locate(position));
+
+        match meeting.timezone {
+            Some(_) => located += 1,
+            None => missing += 1,
+        }
+    }
+
+    if located > 0 {
+        println!("Set the time zone of {located} meetings of {source} from their position");
+    }
+
+    if missing > 0 {
+        eprintln!(
+            "{missing} meetings of {source} have no time zone, set the timezone of the source or configure [timezones]"
+        );
+    }
+}
+
+struct SyncOptions {
+    /// The percentage of meetings a source may lose since the previous sync.
+    max_drop: f64,
+    abort_on_drop: bool,
+    dry_run: bool,
+    /// The names of the sources to sync, every source when empty.
+    sources: Vec<String>,
+    geocode_rules: GeocodeRules,
+}
+
+/// Why the meetings of a source should not replace the previous ones, if they should not.
+fn check_drop(
+    previous_count: usize,
+    meeting_count: usize,
+    options: &SyncOptions,
+) -> Option<String> {
+    if previous_count == 0 || meeting_count >= previous_count {
+        return None;
+    }
+
+    let drop = (previous_count - meeting_count) as f64 * 100.0 / previous_count as f64;
+
+    if drop > options.max_drop {
+        Some(format!(
+            "found {meeting_count} meetings instead of {previous_count}, a drop of {drop:.0}% which exceeds the maximum of {}%",
+            options.max_drop
+        ))
+    } else {
+        None
+    }
+}
+
+async fn mark_source_stale(import: &index::MeetingImport<'_>, source: &str) {
+    match import.mark_stale(source).await {
+        Ok(stale_count) => {
+            println!("Keeping {stale_count} previous meetings of {source}, marked as stale")
+        }
+        Err(e) => eprintln!("Failed to mark the meetings of {source} as stale: {e}"),
+    }
+}
+
+/// Add the meetings of every source to the staging, reporting what changed per source.
 async fn add_meetings_to_index(
-    mut rx: Receiver<FetchMeetingResult>,
+    mut rx: Receiver<SourceResult>,
     import: &mut index::MeetingImport<'_>,
     position_lookup: &position_lookup::PositionLookup,
+    reverse_geocoder: Option<&ReverseGeocoder>,
+    timezone_locator: Option<&TimeZoneLocator>,
+    options: &SyncOptions,
+    report: &mut SyncReport,
 ) {
-    while let Some(result) = rx.recv().await {
+    while let Some(SourceResult { source, result }) = rx.recv().await {
+        let source_report = report.source(&source);
+
         match result {
-            Err(e) => eprintln!("Failed to fetch meetings: {}", e),
-            Ok(mut meetings) => {
+            Err(e) => {
+                eprintln!("Failed to fetch meetings from {source}: {e}");
+                mark_source_stale(import, &source).await;
+
+                source_report.status = SourceStatus::Failed;
+                source_report.message = Some(e.to_string());
+            }
+            Ok(FetchedMeetings {
+                mut meetings,
+                conversion_failures,
+            }) => {
                 let meeting_count = meetings.len();
-                println!("Found {meeting_count} meetings");
+                println!("Found {meeting_count} meetings in {source}");
 
-                lookup_meeting_positions(&mut meetings, position_lookup).await;
-                let result = import
-                    .add_meetings(meetings.iter().map(|m| &m.meeting))
-                    .await;
+                let failed_fields = FieldFailures::group(&conversion_failures);
 
-                if let Err(e) = result {
-                    println!("Failed to add meetings to the staging: {}", e);
-                } else {
-                    println!("Added {meeting_count} meetings to the staging");
+                if !conversion_failures.is_empty() {
+                    eprintln!(
+                        "Failed to convert {} records of {source}",
+                        conversion_failures.len()
+                    );
+
+                    for failed_field in &failed_fields {
+                        eprintln!("  {failed_field}");
+                    }
+                }
+
+                source_report.meetings = meeting_count;
+                source_report.conversion_failures = conversion_failures.len();
+                source_report.failed_fields = failed_fields;
+
+                let drop_reason = match import.previous_count(&source).await {
+                    Ok(previous_count) => check_drop(previous_count, meeting_count, options),
+                    Err(e) => Some(format!("failed to count the previous meetings: {e}")),
+                };
+
+                if let Some(reason) = drop_reason {
+                    eprintln!("Holding back {source}: {reason}");
+                    mark_source_stale(import, &source).await;
+
+                    source_report.status = SourceStatus::HeldBack;
+                    source_report.message = Some(reason);
+                    continue;
+                }
+
+                source_report.rejected_positions = lookup_meeting_positions(
+                    &mut meetings,
+                    position_lookup,
+                    reverse_geocoder,
+                    &options.geocode_rules,
+                )
+                .await;
+
+                if let Some(reverse_geocoder) = reverse_geocoder {
+                    correct_meeting_areas(&mut meetings, reverse_geocoder, &source);
+                }
+
+                locate_meeting_timezones(&mut meetings, timezone_locator, &source);
+
+                // A source that fails halfway must not leave some of its meetings behind
+                let result = match import.savepoint().await {
+                    Ok(()) => {
+                        import
+                            .add_meetings(&source, meetings.iter().map(|m| &m.meeting))
+                            .await
+                    }
+                    Err(e) => Err(e),
+                };
+
+                match result {
+                    Err(e) => {
+                        println!("Failed to add meetings of {source} to the staging: {}", e);
+
+                        if let Err(e) = import.rollback_to_savepoint().await {
+                            eprintln!(
+                                "Failed to undo the meetings of {source} that were added: {e}"
+                            );
+                        }
+
+                        mark_source_stale(import, &source).await;
+
+                        source_report.status = SourceStatus::Failed;
+                        source_report.message = Some(e.to_string());
+                    }
+                    Ok(changes) => {
+                        println!("Added {meeting_count} meetings of {source} to the staging");
+                        source_report.added = changes.added;
+                        source_report.modified = changes.modified;
+
+                        match import.remove_missing_meetings(&source).await {
+                            Ok(removed) => {
+                                println!(
+                                    "Marked {} meetings that disappeared from {source} as removed",
+                                    removed.len()
+                                );
+                                source_report.removed = removed;
+                            }
+                            Err(e) => eprintln!(
+                                "Failed to mark the meetings that disappeared from {source} as removed: {e}"
+                            ),
+                        }
+
+                        if let Err(e) = import.release_savepoint().await {
+                            eprintln!("Failed to keep the meetings of {source}: {e}");
+                        }
+                    }
                 }
             }
         }
@@ -129,25 +729,200 @@ async fn add_meetings_to_index(
 
 async fn sync_index(
     index: &mut index::MeetingIndex,
+    registry: &SourceRegistry,
     position_lookup: &position_lookup::PositionLookup,
-) -> Result<(), index::IndexError> {
+    reverse_geocoder: Option<&ReverseGeocoder>,
+    timezone_locator: Option<&TimeZoneLocator>,
+    options: &SyncOptions,
+) -> Result<SyncReport, index::IndexError> {
     let mut import = index.start_import().await?;
-    import.remove_old_meetings().await?;
+    let mut report = SyncReport::new(import.started_at());
 
     let (tx, rx) = channel(1024);
     join!(
-        source::fetch_all_meetings(tx),
-        add_meetings_to_index(rx, &mut import, position_lookup)
+        registry.fetch_all_meetings(&options.sources, tx),
+        add_meetings_to_index(
+            rx,
+            &mut import,
+            position_lookup,
+            reverse_geocoder,
+            timezone_locator,
+            options,
+            &mut report
+        )
     );
 
     let meeting_count = import.meetings_added();
+    let held_back = report.sources_with_status(SourceStatus::HeldBack);
+
+    if !held_back.is_empty() {
+        eprintln!(
+            "Held back {} sources, their previous meetings were kept: {}",
+            held_back.len(),
+            held_back.join(", ")
+        );
+
+        if options.abort_on_drop {
+            eprintln!(
+                "Refusing to commit the staging to the database because sources were held back"
+            );
+            import.rollback().await?;
+            return Ok(report);
+        }
+    }
 
     if meeting_count > 0 {
-        import.commit().await?;
-        println!("Committed the staging to the database with {meeting_count} meetings total");
+        let reported = report.sources.keys().cloned().collect::<Vec<_>>();
+        let unfetched = registry.unfetched(&options.sources);
+
+        let unreported = import
+            .remove_unreported_sources(&reported, &options.sources, &unfetched)
+            .await?;
+
+        for (source_key, removed) in unreported {
+            let source = source_key.unwrap_or_else(|| String::from("(untracked)"));
+            println!(
+                "Marked {} meetings of {source}, which no longer exists, as removed",
+                removed.len()
+            );
+
+            let source_report = report.source(&source);
+            source_report.status = SourceStatus::Missing;
+            source_report.removed = removed;
+        }
+
+        if options.dry_run {
+            import.rollback().await?;
+            println!(
+                "Rolled back the staging with {meeting_count} meetings total, because this is a dry run"
+            );
+        } else {
+            import.commit().await?;
+            report.committed = true;
+            println!("Committed the staging to the database with {meeting_count} meetings total");
+        }
     } else {
         eprintln!("Refusing to commit the staging to the database because it contains 0 meetings");
     }
 
-    Ok(())
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meeting::Meeting;
+    use std::path::Path;
+    use std::time::Duration;
+
+    fn options(max_drop: f64) -> SyncOptions {
+        SyncOptions {
+            max_drop,
+            abort_on_drop: false,
+            dry_run: false,
+            sources: Vec::new(),
+            geocode_rules: GeocodeRules::default(),
+        }
+    }
+
+    #[test]
+    fn allows_a_drop_up_to_the_maximum() {
+        assert_eq!(check_drop(100, 80, &options(20.0)), None);
+        assert_eq!(check_drop(100, 100, &options(0.0)), None);
+        assert_eq!(check_drop(100, 120, &options(0.0)), None);
+    }
+
+    #[test]
+    fn holds_back_a_larger_drop() {
+        let reason = check_drop(100, 79, &options(20.0)).unwrap();
+
+        assert!(
+            reason.contains("found 79 meetings instead of 100"),
+            "{reason}"
+        );
+        assert!(reason.contains("21%"), "{reason}");
+    }
+
+    #[test]
+    fn holds_back_a_source_that_found_nothing() {
+        assert!(check_drop(10, 0, &options(50.0)).is_some());
+    }
+
+    #[test]
+    fn allows_anything_for_a_new_source() {
+        assert_eq!(check_drop(0, 0, &options(0.0)), None);
+        assert_eq!(check_drop(0, 10, &options(0.0)), None);
+    }
+
+    /// Sync `meetings` as the only meetings of source `a`, returning its report.
+    async fn sync_source(index: &mut index::MeetingIndex, meetings: Vec<Meeting>) -> SyncReport {
+        let position_lookup = position_lookup::PositionLookup::open(
+            Path::new(":memory:"),
+            geocoder::Geocoders {
+                offline: None,
+                network: None,
+                rate_limit: Duration::ZERO,
+            },
+        )
+        .unwrap();
+
+        let (tx, rx) = channel(1);
+        tx.send(SourceResult {
+            source: String::from("a"),
+            result: Ok(meetings
+                .into_iter()
+                .map(|meeting| {
+                    Ok(FetchMeeting {
+                        meeting,
+                        position_query: None,
+                    })
+                })
+                .collect()),
+        })
+        .await
+        .unwrap();
+        drop(tx);
+
+        let mut import = index.start_import().await.unwrap();
+        let mut report = SyncReport::new(import.started_at());
+        add_meetings_to_index(
+            rx,
+            &mut import,
+            &position_lookup,
+            None,
+            None,
+            &options(20.0),
+            &mut report,
+        )
+        .await;
+        import.commit().await.unwrap();
+
+        report
+    }
+
+    #[tokio::test]
+    async fn keeps_the_meetings_of_a_source_that_was_held_back() {
+        let mut index = index::MeetingIndex::open(Path::new(":memory:")).unwrap();
+        let meetings: Vec<Meeting> = (0..10)
+            .map(|i| Meeting::example(&format!("a:{i}")))
+            .collect();
+
+        let report = sync_source(&mut index, meetings.clone()).await;
+        assert_eq!(report.sources["a"].status, SourceStatus::Imported);
+        assert_eq!(report.sources["a"].added.len(), 10);
+
+        let report = sync_source(&mut index, meetings[..5].to_vec()).await;
+        assert_eq!(report.sources["a"].status, SourceStatus::HeldBack);
+        assert!(report.sources["a"].removed.is_empty());
+
+        let results = index
+            .search(&index::SearchOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(results.total, 10);
+        assert!(results
+            .meetings
+            .iter()
+            .all(|meeting| meeting.stale_since == Some(report.started_at)));
+    }
 }
@@ -1,8 +1,11 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use chrono_tz::Tz;
+use std::fmt::{Display, Formatter};
 use std::str::FromStr;
 use std::time::Duration;
 
-use serde::Serialize;
+use serde::{Serialize, Serializer};
+use utoipa::openapi::{ObjectBuilder, Schema, SchemaType};
 use utoipa::ToSchema;
 
 #[derive(Debug, Clone, PartialEq, PartialOrd, Default, Serialize, ToSchema)]
@@ -20,7 +23,7 @@ impl Position {
     }
 }
 
-#[derive(Debug, Clone, Serialize, ToSchema)]
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
 pub struct Location {
     pub position: Option<Position>,
     pub name: Option<String>,
@@ -30,13 +33,13 @@ pub struct Location {
     pub address: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, ToSchema)]
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
 pub struct Contact {
     pub email: Option<String>,
     pub phone: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, ToSchema)]
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
 pub enum WeekDay {
     Monday,
     Tuesday,
@@ -74,14 +77,100 @@ impl WeekDay {
     }
 }
 
-#[derive(Debug, Clone, Serialize, ToSchema)]
+#[derive(Debug, Clone)]
+pub enum WeekDayParseError {
+    UnknownDay,
+}
+
+impl FromStr for WeekDay {
+    type Err = WeekDayParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Monday" => Ok(Self::Monday),
+            "Tuesday" => Ok(Self::Tuesday),
+            "Wednesday" => Ok(Self::Wednesday),
+            "Thursday" => Ok(Self::Thursday),
+            "Friday" => Ok(Self::Friday),
+            "Saturday" => Ok(Self::Saturday),
+            "Sunday" => Ok(Self::Sunday),
+            _ => Err(WeekDayParseError::UnknownDay),
+        }
+    }
+}
+
+impl From<chrono::Weekday> for WeekDay {
+    fn from(day: chrono::Weekday) -> Self {
+        Self::from_day_index(day.num_days_from_monday() as u8)
+    }
+}
+
+/// When a meeting takes place. The TSML, BMLT and NA Holland feeds only give a weekday and a
+/// time, so their meetings are always `Recurring`, the other variants are for sources that
+/// report how often a meeting takes place.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
 pub enum MeetingTime {
+    /// Every week on the given day.
     #[serde(rename = "recurring")]
     Recurring {
         day: WeekDay,
         hour: i32,
         minute: i32,
     },
+
+    /// Once a month on the nth weekday, where a `week` of -1 means the last one of the month.
+    #[serde(rename = "monthly")]
+    Monthly {
+        week: i8,
+        day: WeekDay,
+        hour: i32,
+        minute: i32,
+    },
+
+    /// Every other week, on the weekday of `anchor` and every 14 days before and after it.
+    #[serde(rename = "biweekly")]
+    Biweekly {
+        anchor: NaiveDate,
+        hour: i32,
+        minute: i32,
+    },
+
+    /// A single event on the given date, like a convention or a workshop.
+    #[serde(rename = "once")]
+    Once {
+        date: NaiveDate,
+        hour: i32,
+        minute: i32,
+    },
+}
+
+impl MeetingTime {
+    pub fn day(&self) -> WeekDay {
+        match self {
+            MeetingTime::Recurring { day, .. } | MeetingTime::Monthly { day, .. } => day.clone(),
+            MeetingTime::Biweekly { anchor: date, .. } | MeetingTime::Once { date, .. } => {
+                date.weekday().into()
+            }
+        }
+    }
+
+    pub fn hour(&self) -> i32 {
+        match self {
+            MeetingTime::Recurring { hour, .. }
+            | MeetingTime::Monthly { hour, .. }
+            | MeetingTime::Biweekly { hour, .. }
+            | MeetingTime::Once { hour, .. } => *hour,
+        }
+    }
+
+    pub fn minute(&self) -> i32 {
+        match self {
+            MeetingTime::Recurring { minute, .. }
+            | MeetingTime::Monthly { minute, .. }
+            | MeetingTime::Biweekly { minute, .. }
+            | MeetingTime::Once { minute, .. } => *minute,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, ToSchema)]
@@ -93,15 +182,17 @@ pub enum Organization {
     NarcoticsAnonymous,
 }
 
-impl ToString for Organization {
-    fn to_string(&self) -> String {
-        match self {
-            Organization::AnonymousAlcoholics => String::from("AnonymousAlcoholics"),
-            Organization::DebtorsAnonymous => String::from("DebtorsAnonymous"),
-            Organization::CrystalMethAnonymous => String::from("CrystalMethAnonymous"),
-            Organization::CodependentsAnonymous => String::from("CodependentsAnonymous"),
-            Organization::NarcoticsAnonymous => String::from("NarcoticsAnonymous"),
-        }
+impl Display for Organization {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Organization::AnonymousAlcoholics => "AnonymousAlcoholics",
+            Organization::DebtorsAnonymous => "DebtorsAnonymous",
+            Organization::CrystalMethAnonymous => "CrystalMethAnonymous",
+            Organization::CodependentsAnonymous => "CodependentsAnonymous",
+            Organization::NarcoticsAnonymous => "NarcoticsAnonymous",
+        };
+
+        f.write_str(name)
     }
 }
 
@@ -125,15 +216,170 @@ impl FromStr for Organization {
     }
 }
 
-#[derive(Debug, Clone, Serialize, ToSchema)]
+/// Serialized as the string of `Display`, like `Open` or `Language:nl`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MeetingType {
+    Open,
+    Closed,
+    Speaker,
+    Discussion,
+    StepStudy,
+    TraditionStudy,
+    Literature,
+    Meditation,
+    Beginners,
+    Women,
+    Men,
+    Lgbtq,
+    YoungPeople,
+    WheelchairAccess,
+    SignLanguage,
+    Secular,
+    NonSmoking,
+    /// The spoken language as an ISO 639-1 code.
+    Language(String),
+}
+
+impl Display for MeetingType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            MeetingType::Open => "Open",
+            MeetingType::Closed => "Closed",
+            MeetingType::Speaker => "Speaker",
+            MeetingType::Discussion => "Discussion",
+            MeetingType::StepStudy => "StepStudy",
+            MeetingType::TraditionStudy => "TraditionStudy",
+            MeetingType::Literature => "Literature",
+            MeetingType::Meditation => "Meditation",
+            MeetingType::Beginners => "Beginners",
+            MeetingType::Women => "Women",
+            MeetingType::Men => "Men",
+            MeetingType::Lgbtq => "Lgbtq",
+            MeetingType::YoungPeople => "YoungPeople",
+            MeetingType::WheelchairAccess => "WheelchairAccess",
+            MeetingType::SignLanguage => "SignLanguage",
+            MeetingType::Secular => "Secular",
+            MeetingType::NonSmoking => "NonSmoking",
+            MeetingType::Language(code) => return write!(f, "Language:{code}"),
+        };
+
+        f.write_str(name)
+    }
+}
+
+impl Serialize for MeetingType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl ToSchema for MeetingType {
+    fn schema() -> Schema {
+        ObjectBuilder::new()
+            .schema_type(SchemaType::String)
+            .description(Some(
+                "A type like `Open` or `StepStudy`, or the spoken language as `Language:` \
+                followed by an ISO 639-1 code.",
+            ))
+            .example(Some("Language:nl".into()))
+            .into()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum MeetingTypeParseError {
+    UnknownType,
+}
+
+impl FromStr for MeetingType {
+    type Err = MeetingTypeParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(code) = s.strip_prefix("Language:") {
+            return Ok(Self::Language(code.to_lowercase()));
+        }
+
+        match s {
+            "Open" => Ok(Self::Open),
+            "Closed" => Ok(Self::Closed),
+            "Speaker" => Ok(Self::Speaker),
+            "Discussion" => Ok(Self::Discussion),
+            "StepStudy" => Ok(Self::StepStudy),
+            "TraditionStudy" => Ok(Self::TraditionStudy),
+            "Literature" => Ok(Self::Literature),
+            "Meditation" => Ok(Self::Meditation),
+            "Beginners" => Ok(Self::Beginners),
+            "Women" => Ok(Self::Women),
+            "Men" => Ok(Self::Men),
+            "Lgbtq" => Ok(Self::Lgbtq),
+            "YoungPeople" => Ok(Self::YoungPeople),
+            "WheelchairAccess" => Ok(Self::WheelchairAccess),
+            "SignLanguage" => Ok(Self::SignLanguage),
+            "Secular" => Ok(Self::Secular),
+            "NonSmoking" => Ok(Self::NonSmoking),
+            _ => Err(MeetingTypeParseError::UnknownType),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, ToSchema)]
+pub enum AttendanceMode {
+    InPerson,
+    Online,
+    Hybrid,
+    /// The meeting does not take place for now, for example because the location is closed.
+    TemporarilyInactive,
+}
+
+impl AttendanceMode {
+    pub fn is_online(&self) -> bool {
+        matches!(self, AttendanceMode::Online | AttendanceMode::Hybrid)
+    }
+}
+
+impl Display for AttendanceMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            AttendanceMode::InPerson => "InPerson",
+            AttendanceMode::Online => "Online",
+            AttendanceMode::Hybrid => "Hybrid",
+            AttendanceMode::TemporarilyInactive => "TemporarilyInactive",
+        };
+
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum AttendanceModeParseError {
+    UnknownMode,
+}
+
+impl FromStr for AttendanceMode {
+    type Err = AttendanceModeParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "InPerson" => Ok(Self::InPerson),
+            "Online" => Ok(Self::Online),
+            "Hybrid" => Ok(Self::Hybrid),
+            "TemporarilyInactive" => Ok(Self::TemporarilyInactive),
+            _ => Err(AttendanceModeParseError::UnknownMode),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
 pub struct OnlineOptions {
     pub url: Option<String>,
     pub notes: Option<String>,
-    pub is_online: bool,
 }
 
-#[derive(Debug, Clone, Serialize, ToSchema)]
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
 pub struct Meeting {
+    /// A stable identifier made up of the source namespace and the id the source uses.
+    #[schema(example = "na-holland.nl:42")]
+    pub id: String,
     pub name: String,
     pub org: Organization,
     pub notes: Option<String>,
@@ -143,9 +389,115 @@ pub struct Meeting {
     pub contact: Contact,
     pub location: Location,
 
+    pub attendance: AttendanceMode,
     pub online_options: OnlineOptions,
 
+    pub types: Vec<MeetingType>,
+
     pub time: MeetingTime,
+    /// The IANA time zone the meeting time is expressed in.
+    #[schema(value_type = Option<String>, example = "Europe/Amsterdam")]
+    pub timezone: Option<Tz>,
 
     pub duration: Option<Duration>,
 }
+
+impl Meeting {
+    pub fn make_id(namespace: &str, source_id: impl Display) -> String {
+        format!("{namespace}:{source_id}")
+    }
+
+    /// Compare everything except `updated_at` and the order of the types.
+    pub fn same_content(&self, other: &Meeting) -> bool {
+        let mut a = self.clone();
+        let mut b = other.clone();
+
+        a.updated_at = b.updated_at;
+        a.types.sort();
+        b.types.sort();
+
+        a == b
+    }
+}
+
+/// Derive an id namespace from the url of a source, e.g. `https://www.example.org/bmlt/` becomes
+/// `example.org-bmlt`.
+pub fn url_namespace(url: &str) -> String {
+    let Ok(url) = reqwest::Url::parse(url) else {
+        return url.to_string();
+    };
+
+    let host = url.host_str().unwrap_or_default().trim_start_matches("www.");
+    let path = url.path().trim_matches('/');
+
+    if path.is_empty() {
+        host.to_string()
+    } else {
+        format!("{host}-{}", path.replace('/', "-"))
+    }
+}
+
+/// Parse an IANA time zone name as given by a source, ignoring empty or unknown values.
+pub fn parse_timezone(name: &str) -> Option<Tz> {
+    name.trim().parse().ok()
+}
+
+#[cfg(test)]
+impl Meeting {
+    /// A weekly in-person meeting of `id`, for tests.
+    pub fn example(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            name: format!("Meeting {id}"),
+            org: Organization::AnonymousAlcoholics,
+            notes: None,
+            source: String::from("https://example.org"),
+            updated_at: Utc::now(),
+            contact: Contact {
+                email: None,
+                phone: None,
+            },
+            location: Location {
+                position: Some(Position::new(52.0, 5.0)),
+                name: Some(String::from("Church")),
+                notes: None,
+                country: Some(String::from("NL")),
+                region: None,
+                address: None,
+            },
+            attendance: AttendanceMode::InPerson,
+            online_options: OnlineOptions {
+                url: None,
+                notes: None,
+            },
+            types: vec![MeetingType::Open],
+            time: MeetingTime::Recurring {
+                day: WeekDay::Monday,
+                hour: 20,
+                minute: 0,
+            },
+            timezone: Some(chrono_tz::Europe::Amsterdam),
+            duration: Some(Duration::from_secs(3600)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn meeting_types_serialize_as_their_string_form() {
+        let types = vec![MeetingType::Open, MeetingType::Language(String::from("nl"))];
+
+        let json = serde_json::to_string(&types).unwrap();
+        assert_eq!(json, r#"["Open","Language:nl"]"#);
+
+        let parsed: Vec<MeetingType> = serde_json::from_str::<Vec<String>>(&json)
+            .unwrap()
+            .iter()
+            .map(|t| t.parse().unwrap())
+            .collect();
+        assert_eq!(parsed, types);
+    }
+}
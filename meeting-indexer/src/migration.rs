@@ -0,0 +1,111 @@
+use rusqlite::Connection;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum MigrationError {
+    #[error("SQL error: {0}")]
+    SqliteError(#[from] rusqlite::Error),
+
+    #[error("The database is at version {found}, but this binary only supports up to version {supported}")]
+    DatabaseTooNew { found: usize, supported: usize },
+}
+
+/// Bring a database up to date by running every migration after the one stored in its
+/// `user_version`. Migration `n` (counting from 1) leaves the database at version `n`.
+pub fn migrate(conn: &mut Connection, migrations: &[&str]) -> Result<(), MigrationError> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    if version > migrations.len() {
+        return Err(MigrationError::DatabaseTooNew {
+            found: version,
+            supported: migrations.len(),
+        });
+    }
+
+    for (index, migration) in migrations.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", index + 1)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIGRATIONS: &[&str] = &[
+        "CREATE TABLE steps (step INTEGER NOT NULL);
+        INSERT INTO steps VALUES (1);",
+        "INSERT INTO steps VALUES (2);",
+        "INSERT INTO steps VALUES (3);",
+    ];
+
+    fn steps(conn: &Connection) -> Vec<i64> {
+        conn.prepare("SELECT step FROM steps ORDER BY rowid")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn version(conn: &Connection) -> usize {
+        conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn runs_every_migration_in_order() {
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        migrate(&mut conn, MIGRATIONS).unwrap();
+
+        assert_eq!(steps(&conn), vec![1, 2, 3]);
+        assert_eq!(version(&conn), 3);
+    }
+
+    #[test]
+    fn only_runs_the_migrations_after_the_version() {
+        let mut conn = Connection::open_in_memory().unwrap();
+
+        migrate(&mut conn, &MIGRATIONS[..1]).unwrap();
+        assert_eq!(version(&conn), 1);
+
+        migrate(&mut conn, MIGRATIONS).unwrap();
+        migrate(&mut conn, MIGRATIONS).unwrap();
+
+        assert_eq!(steps(&conn), vec![1, 2, 3]);
+        assert_eq!(version(&conn), 3);
+    }
+
+    #[test]
+    fn keeps_the_version_of_a_failed_migration() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let migrations = [MIGRATIONS[0], "INSERT INTO missing VALUES (2);"];
+
+        assert!(migrate(&mut conn, &migrations).is_err());
+
+        assert_eq!(steps(&conn), vec![1]);
+        assert_eq!(version(&conn), 1);
+    }
+
+    #[test]
+    fn refuses_a_database_that_is_too_new() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn, MIGRATIONS).unwrap();
+
+        let result = migrate(&mut conn, &MIGRATIONS[..2]);
+
+        assert!(matches!(
+            result,
+            Err(MigrationError::DatabaseTooNew {
+                found: 3,
+                supported: 2
+            })
+        ));
+        assert_eq!(steps(&conn), vec![1, 2, 3]);
+    }
+}
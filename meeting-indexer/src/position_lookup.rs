@@ -1,81 +1,182 @@
+use crate::geocoder::{Geocode, GeocodeError, Geocoder, Geocoders};
 use crate::meeting::Position;
-use chrono::Utc;
-use rusqlite::{params, Connection, OpenFlags};
-use serde::Deserialize;
+use crate::migration::{migrate, MigrationError};
+use chrono::{DateTime, NaiveDate, Utc};
+use rusqlite::{params, params_from_iter, Connection, OpenFlags, Row};
+use std::io::{Read, Write};
 use std::path::Path;
-use std::time::{Duration, SystemTime};
+use std::time::SystemTime;
 use thiserror::Error;
 use tokio::sync::{Mutex, MutexGuard};
 use tokio::time::sleep;
 
-const API_RATE_LIMIT: Duration = Duration::from_secs(2);
-
 #[derive(Debug, Error)]
 pub enum PositionLookupError {
     #[error("SQL error: {0}")]
     SqliteError(#[from] rusqlite::Error),
 
-    #[error("HTTP Request error: {0}")]
-    HttpRequestError(#[from] reqwest::Error),
+    #[error("Geocode error: {0}")]
+    GeocodeError(#[from] GeocodeError),
+
+    #[error("Migration error: {0}")]
+    MigrationError(#[from] MigrationError),
+
+    #[error("CSV error: {0}")]
+    CsvError(#[from] csv::Error),
 
-    #[error("JSON parse error: {0}")]
-    JsonParseError(#[from] serde_json::Error),
+    #[error("Invalid record on line {line}: {reason}")]
+    InvalidRecord { line: u64, reason: String },
+
+    #[error("No network geocoder is configured")]
+    NoGeocoder,
 }
 
+/// The schema of positions.db, one entry per version.
+const MIGRATIONS: &[&str] = &[
+    // Same as the schema from before versioning, so existing caches are kept.
+    "CREATE TABLE IF NOT EXISTS positions (
+        query TEXT NOT NULL PRIMARY KEY,
+        latitude REAL NULL,
+        longitude REAL NULL,
+        requested_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+    );",
+    "ALTER TABLE positions ADD COLUMN precision TEXT NULL;
+    ALTER TABLE positions ADD COLUMN confidence REAL NULL;
+    ALTER TABLE positions ADD COLUMN country_code TEXT NULL;
+    ALTER TABLE positions ADD COLUMN country TEXT NULL;",
+];
+
 #[derive(Debug)]
 pub struct PositionLookupValue {
-    pub position: Option<Position>,
+    pub geocode: Option<Geocode>,
     pub cached: bool,
+    /// Found in the gazetteer, these are not cached.
+    pub offline: bool,
+}
+
+/// An entry of the cache.
+#[derive(Debug)]
+pub struct CachedPosition {
+    pub query: String,
+    pub geocode: Option<Geocode>,
+    pub requested_at: DateTime<Utc>,
+}
+
+/// Selects entries of the cache, every entry when empty.
+#[derive(Debug, Default)]
+pub struct CacheFilter {
+    /// Queries containing this text, compared case insensitively.
+    pub matching: Option<String>,
+    /// Only the queries that did not find a position.
+    pub null: bool,
+    /// Requested before this date.
+    pub before: Option<NaiveDate>,
 }
 
+impl CacheFilter {
+    fn where_clause(&self) -> (String, Vec<String>) {
+        let mut conditions = vec![String::from("1")];
+        let mut params = Vec::new();
+
+        if let Some(matching) = &self.matching {
+            params.push(format!("%{}%", escape_like(matching)));
+            conditions.push(format!("`query` LIKE ?{} ESCAPE '\\'", params.len()));
+        }
+
+        if self.null {
+            conditions.push(String::from("(latitude IS NULL OR longitude IS NULL)"));
+        }
+
+        if let Some(before) = self.before {
+            params.push(before.to_string());
+            conditions.push(format!("requested_at < ?{}", params.len()));
+        }
+
+        (conditions.join(" AND "), params)
+    }
+}
+
+/// Match `%`, `_` and `\` literally in a LIKE pattern with `ESCAPE '\'`.
+pub(crate) fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// The columns of the CSV export, which are also expected by the import.
+const CSV_HEADER: [&str; 8] = [
+    "query",
+    "latitude",
+    "longitude",
+    "precision",
+    "confidence",
+    "country_code",
+    "country",
+    "requested_at",
+];
+
 pub struct PositionLookup {
     last_api_request: Mutex<SystemTime>,
     cache_conn: Connection,
+    geocoders: Geocoders,
 }
 
 impl PositionLookup {
-    pub fn open(path: &Path) -> Result<Self, PositionLookupError> {
+    pub fn open(path: &Path, geocoders: Geocoders) -> Result<Self, PositionLookupError> {
         let mut conn = Connection::open_with_flags(
             path,
             OpenFlags::SQLITE_OPEN_CREATE | OpenFlags::SQLITE_OPEN_READ_WRITE,
         )?;
 
-        Self::migrate(&mut conn)?;
+        migrate(&mut conn, MIGRATIONS)?;
 
         Ok(Self {
             cache_conn: conn,
             last_api_request: Mutex::new(SystemTime::now()),
+            geocoders,
         })
     }
 
-    fn migrate(conn: &mut Connection) -> Result<(), PositionLookupError> {
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS positions (
-            query TEXT NOT NULL PRIMARY KEY,
-            latitude REAL NULL,
-            longitude REAL NULL,
-            requested_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
-        )",
-            params![],
-        )?;
-
+    /// Keep every change to the cache from now on in a transaction that is rolled back when the
+    /// lookup is dropped, so a dry run can geocode without writing to the cache.
+    pub fn discard_changes(&self) -> Result<(), PositionLookupError> {
+        self.cache_conn.execute_batch("BEGIN")?;
         Ok(())
     }
 
     pub async fn search(&self, query: &str) -> Result<PositionLookupValue, PositionLookupError> {
+        if let Some(offline) = &self.geocoders.offline {
+            if let Some(geocode) = offline.search(query).await? {
+                return Ok(PositionLookupValue {
+                    geocode: Some(geocode),
+                    cached: false,
+                    offline: true,
+                });
+            }
+        }
+
+        let Some(geocoder) = &self.geocoders.network else {
+            return Ok(PositionLookupValue {
+                geocode: None,
+                cached: false,
+                offline: true,
+            });
+        };
+
         let cached = self.get_cached_position(query)?;
 
-        if let Some(position) = cached {
-            return Ok(position);
+        if let Some(value) = cached {
+            return Ok(value);
         }
 
-        let position = self.get_position_from_api(query).await?;
+        let geocode = self.get_position_from_api(geocoder.as_ref(), query).await?;
 
-        self.set_cached_position(query, &position)?;
+        self.set_cached_position(query, &geocode)?;
 
         Ok(PositionLookupValue {
-            position,
+            geocode,
             cached: false,
+            offline: false,
         })
     }
 
@@ -91,14 +192,10 @@ WHERE `query` = ? AND requested_at > date('now', '-1 month')",
         )?;
 
         let mut rows = stmt.query_map(params![query], |row| {
-            let position = match (row.get("latitude")?, row.get("longitude")?) {
-                (Some(latitude), Some(longitude)) => Some(Position::new(latitude, longitude)),
-                _ => None,
-            };
-
             Ok(PositionLookupValue {
-                position,
+                geocode: geocode_from_row(row)?,
                 cached: true,
+                offline: false,
             })
         })?;
 
@@ -112,46 +209,175 @@ WHERE `query` = ? AND requested_at > date('now', '-1 month')",
     fn set_cached_position(
         &self,
         query: &str,
-        position: &Option<Position>,
+        geocode: &Option<Geocode>,
     ) -> Result<(), PositionLookupError> {
-        self.cache_conn.execute("INSERT OR REPLACE INTO positions (`query`, latitude, longitude, requested_at) values(?, ?, ?, ?)", params![
+        self.cache_conn.execute("INSERT OR REPLACE INTO positions (`query`, latitude, longitude, requested_at, precision, confidence, country_code, country) values(?, ?, ?, ?, ?, ?, ?, ?)", params![
             query,
-            position.as_ref().map(|p| p.latitude),
-            position.as_ref().map(|p| p.longitude),
-            Utc::now()
+            geocode.as_ref().map(|g| g.position.latitude),
+            geocode.as_ref().map(|g| g.position.longitude),
+            Utc::now(),
+            geocode.as_ref().and_then(|g| g.precision).map(|p| p.to_string()),
+            geocode.as_ref().and_then(|g| g.confidence),
+            geocode.as_ref().and_then(|g| g.country_code.as_deref()),
+            geocode.as_ref().and_then(|g| g.country.as_deref()),
         ])?;
         Ok(())
     }
 
+    /// The entries of the cache, in the order of their queries.
+    pub fn cached_positions(
+        &self,
+        filter: &CacheFilter,
+    ) -> Result<Vec<CachedPosition>, PositionLookupError> {
+        let (condition, params) = filter.where_clause();
+        let mut stmt = self.cache_conn.prepare(&format!(
+            "SELECT * FROM positions WHERE {condition} ORDER BY `query`"
+        ))?;
+
+        let rows = stmt.query_map(params_from_iter(params), |row| {
+            Ok(CachedPosition {
+                query: row.get("query")?,
+                geocode: geocode_from_row(row)?,
+                requested_at: row.get("requested_at")?,
+            })
+        })?;
+
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Remove entries from the cache, returns how many were removed.
+    pub fn purge(&self, filter: &CacheFilter) -> Result<usize, PositionLookupError> {
+        let (condition, params) = filter.where_clause();
+
+        Ok(self.cache_conn.execute(
+            &format!("DELETE FROM positions WHERE {condition}"),
+            params_from_iter(params),
+        )?)
+    }
+
+    /// Ask the network geocoder again, ignoring the cache, and cache the new result.
+    pub async fn resolve(&self, query: &str) -> Result<Option<Geocode>, PositionLookupError> {
+        let geocoder = self
+            .geocoders
+            .network
+            .as_ref()
+            .ok_or(PositionLookupError::NoGeocoder)?;
+
+        let geocode = self.get_position_from_api(geocoder.as_ref(), query).await?;
+        self.set_cached_position(query, &geocode)?;
+
+        Ok(geocode)
+    }
+
+    /// Write the entries of the cache as CSV, returns how many were written.
+    pub fn export_csv(
+        &self,
+        filter: &CacheFilter,
+        output: impl Write,
+    ) -> Result<usize, PositionLookupError> {
+        let mut writer = csv::Writer::from_writer(output);
+        writer.write_record(CSV_HEADER)?;
+
+        let entries = self.cached_positions(filter)?;
+
+        for entry in &entries {
+            let geocode = entry.geocode.as_ref();
+            let field = |value: Option<String>| value.unwrap_or_default();
+
+            writer.write_record([
+                entry.query.clone(),
+                field(geocode.map(|g| g.position.latitude.to_string())),
+                field(geocode.map(|g| g.position.longitude.to_string())),
+                field(geocode.and_then(|g| g.precision).map(|p| p.to_string())),
+                field(geocode.and_then(|g| g.confidence).map(|c| c.to_string())),
+                field(geocode.and_then(|g| g.country_code.clone())),
+                field(geocode.and_then(|g| g.country.clone())),
+                entry.requested_at.to_rfc3339(),
+            ])?;
+        }
+
+        writer.flush().map_err(csv::Error::from)?;
+
+        Ok(entries.len())
+    }
+
+    /// Add the entries of a CSV export to the cache, replacing entries with the same query.
+    /// Returns how many were imported.
+    pub fn import_csv(&mut self, input: impl Read) -> Result<usize, PositionLookupError> {
+        let mut reader = csv::Reader::from_reader(input);
+
+        if reader.headers()? != &csv::StringRecord::from(CSV_HEADER.to_vec()) {
+            return Err(PositionLookupError::InvalidRecord {
+                line: 1,
+                reason: format!("expected the columns {}", CSV_HEADER.join(",")),
+            });
+        }
+
+        let tx = self.cache_conn.transaction()?;
+        let mut count = 0;
+
+        for record in reader.records() {
+            let record = record?;
+            let line = record.position().map(|p| p.line()).unwrap_or_default();
+            let invalid = |column: &str| PositionLookupError::InvalidRecord {
+                line,
+                reason: format!("invalid {column}"),
+            };
+
+            let field = |index: usize| Some(&record[index]).filter(|value| !value.is_empty());
+            let number = |index: usize| {
+                field(index)
+                    .map(|value| value.parse::<f64>().map_err(|_| invalid(CSV_HEADER[index])))
+                    .transpose()
+            };
+
+            let requested_at: DateTime<Utc> = record[7]
+                .parse::<DateTime<chrono::FixedOffset>>()
+                .map_err(|_| invalid("requested_at"))?
+                .into();
+
+            tx.execute(
+                "INSERT OR REPLACE INTO positions (`query`, latitude, longitude, precision, confidence, country_code, country, requested_at) values(?, ?, ?, ?, ?, ?, ?, ?)",
+                params![
+                    &record[0],
+                    number(1)?,
+                    number(2)?,
+                    field(3),
+                    number(4)?,
+                    field(5),
+                    field(6),
+                    requested_at
+                ],
+            )?;
+            count += 1;
+        }
+
+        tx.commit()?;
+
+        Ok(count)
+    }
+
     async fn get_position_from_api(
         &self,
+        geocoder: &dyn Geocoder,
         query: &str,
-    ) -> Result<Option<Position>, PositionLookupError> {
+    ) -> Result<Option<Geocode>, PositionLookupError> {
         let mut lock = self.api_lock_and_ratelimit().await;
 
-        let res: ApiData = reqwest::get(format!(
-            "https://positionstack.com/geo_api.php?query={query}"
-        ))
-        .await?
-        .json()
-        .await?;
+        let geocode = geocoder.search(query).await;
 
         *lock = SystemTime::now();
 
-        if let Some(record) = res.data.into_iter().next() {
-            Ok(Some(record.into()))
-        } else {
-            Ok(None)
-        }
+        Ok(geocode?)
     }
 
     async fn api_lock_and_ratelimit(&self) -> MutexGuard<'_, SystemTime> {
         let lock = self.last_api_request.lock().await;
         let now = SystemTime::now();
 
-        let elapsed = now.duration_since(lock.clone()).unwrap();
+        let elapsed = now.duration_since(*lock).unwrap();
 
-        if let Some(sleep_time) = API_RATE_LIMIT.checked_sub(elapsed) {
+        if let Some(sleep_time) = self.geocoders.rate_limit.checked_sub(elapsed) {
             sleep(sleep_time).await;
         }
 
@@ -159,21 +385,176 @@ WHERE `query` = ? AND requested_at > date('now', '-1 month')",
     }
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct ApiData {
-    pub data: Vec<ApiRecord>,
+fn geocode_from_row(row: &Row) -> Result<Option<Geocode>, rusqlite::Error> {
+    let geocode = match (row.get("latitude")?, row.get("longitude")?) {
+        (Some(latitude), Some(longitude)) => Some(Geocode {
+            position: Position::new(latitude, longitude),
+            precision: row
+                .get::<_, Option<String>>("precision")?
+                .and_then(|precision| precision.parse().ok()),
+            confidence: row.get("confidence")?,
+            country_code: row.get("country_code")?,
+            country: row.get("country")?,
+        }),
+        _ => None,
+    };
+
+    Ok(geocode)
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct ApiRecord {
-    pub latitude: f64,
-    pub longitude: f64,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geocoder::Precision;
+    use std::time::Duration;
+
+    fn position_lookup() -> PositionLookup {
+        let geocoders = Geocoders {
+            offline: None,
+            network: None,
+            rate_limit: Duration::ZERO,
+        };
+
+        PositionLookup::open(Path::new(":memory:"), geocoders).unwrap()
+    }
+
+    const EXPORT: &str =
+        "query,latitude,longitude,precision,confidence,country_code,country,requested_at
+\"Damrak 1, Amsterdam\",52.37,4.89,rooftop,0.9,NL,Nederland,2024-01-02T03:04:05+00:00
+Nowhere,,,,,,,2024-01-02T03:04:05Z
+";
+
+    #[test]
+    fn discards_changes_after_a_dry_run() {
+        let path = std::env::temp_dir().join(format!("positions-{}.db", std::process::id()));
+        let geocode = Geocode {
+            position: Position::new(52.37, 4.89),
+            precision: Some(Precision::Rooftop),
+            confidence: None,
+            country_code: None,
+            country: None,
+        };
+
+        {
+            let geocoders = Geocoders {
+                offline: None,
+                network: None,
+                rate_limit: Duration::ZERO,
+            };
+            let position_lookup = PositionLookup::open(&path, geocoders).unwrap();
+            position_lookup.discard_changes().unwrap();
+
+            position_lookup
+                .set_cached_position("Damrak 1, Amsterdam", &Some(geocode))
+                .unwrap();
+            assert_eq!(
+                queries(&position_lookup, &CacheFilter::default()),
+                ["Damrak 1, Amsterdam"]
+            );
+        }
+
+        let geocoders = Geocoders {
+            offline: None,
+            network: None,
+            rate_limit: Duration::ZERO,
+        };
+        let position_lookup = PositionLookup::open(&path, geocoders).unwrap();
+        let remaining = queries(&position_lookup, &CacheFilter::default());
+
+        drop(position_lookup);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(remaining.is_empty(), "{remaining:?}");
+    }
+
+    fn queries(position_lookup: &PositionLookup, filter: &CacheFilter) -> Vec<String> {
+        position_lookup
+            .cached_positions(filter)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.query)
+            .collect()
+    }
+
+    #[test]
+    fn imports_an_export() {
+        let mut position_lookup = position_lookup();
+
+        assert_eq!(position_lookup.import_csv(EXPORT.as_bytes()).unwrap(), 2);
+
+        let entries = position_lookup
+            .cached_positions(&CacheFilter::default())
+            .unwrap();
+        let geocode = entries[0].geocode.as_ref().unwrap();
+
+        assert_eq!(entries[0].query, "Damrak 1, Amsterdam");
+        assert_eq!(geocode.position, Position::new(52.37, 4.89));
+        assert_eq!(geocode.precision, Some(Precision::Rooftop));
+        assert_eq!(geocode.confidence, Some(0.9));
+        assert_eq!(geocode.country_code.as_deref(), Some("NL"));
+        assert_eq!(entries[1].query, "Nowhere");
+        assert!(entries[1].geocode.is_none());
+
+        let mut exported = Vec::new();
+        position_lookup
+            .export_csv(&CacheFilter::default(), &mut exported)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(exported).unwrap(),
+            EXPORT.replace("Z\n", "+00:00\n")
+        );
+    }
+
+    #[test]
+    fn refuses_unknown_columns() {
+        let mut position_lookup = position_lookup();
+
+        let result = position_lookup.import_csv("query,latitude,longitude\nx,1,2\n".as_bytes());
+
+        assert!(matches!(
+            result,
+            Err(PositionLookupError::InvalidRecord { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn refuses_an_invalid_record_without_importing_any() {
+        let mut position_lookup = position_lookup();
+        let export = EXPORT.replace("52.37", "north");
+
+        let result = position_lookup.import_csv(export.as_bytes());
+
+        assert!(matches!(
+            result,
+            Err(PositionLookupError::InvalidRecord { line: 2, .. })
+        ));
+        assert!(queries(&position_lookup, &CacheFilter::default()).is_empty());
+    }
+
+    #[test]
+    fn matches_wildcards_literally() {
+        let mut position_lookup = position_lookup();
+        let export =
+            "query,latitude,longitude,precision,confidence,country_code,country,requested_at
+100% Club,,,,,,,2024-01-02T03:04:05Z
+100 Club,,,,,,,2024-01-02T03:04:05Z
+a_b,,,,,,,2024-01-02T03:04:05Z
+axb,,,,,,,2024-01-02T03:04:05Z
+a\\b,,,,,,,2024-01-02T03:04:05Z
+";
+        position_lookup.import_csv(export.as_bytes()).unwrap();
+
+        let matching = |text: &str| {
+            let filter = CacheFilter {
+                matching: Some(text.to_string()),
+                ..Default::default()
+            };
+            queries(&position_lookup, &filter)
+        };
 
-impl Into<Position> for ApiRecord {
-    fn into(self) -> Position {
-        Position::new(self.latitude, self.longitude)
+        assert_eq!(matching("0%"), vec!["100% Club"]);
+        assert_eq!(matching("a_"), vec!["a_b"]);
+        assert_eq!(matching("a\\"), vec!["a\\b"]);
+        assert_eq!(matching("CLUB"), vec!["100 Club", "100% Club"]);
     }
 }
@@ -6,13 +6,19 @@ use actix_web::http::StatusCode;
 use actix_web::{
     get, middleware::Logger, web, App, HttpResponse, HttpServer, Responder, ResponseError,
 };
+use chrono::NaiveTime;
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use thiserror::Error;
 use utoipa::{IntoParams, OpenApi};
 use utoipa_swagger_ui::SwaggerUi;
 
 use crate::index::*;
 use crate::meeting;
 
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
 #[derive(Serialize)]
 struct ApiError {
     message: String,
@@ -28,12 +34,135 @@ struct SearchQuery {
 
     /// The maximum distance in kilometers.
     distance: Option<f64>,
+
+    /// Only include meetings inside this box, given as `west,south,east,north` in degrees. A box
+    /// with a west edge greater than its east edge crosses the antimeridian.
+    bbox: Option<String>,
+
+    /// Search the name, notes and location of meetings, the best matches come first.
+    q: Option<String>,
+
+    /// A comma separated list of meeting types, all of which a meeting must have.
+    types: Option<String>,
+
+    /// A comma separated list of attendance modes, one of which a meeting must have.
+    attendance: Option<String>,
+
+    /// A comma separated list of organizations, e.g. `NarcoticsAnonymous,AnonymousAlcoholics`.
+    orgs: Option<String>,
+
+    /// A comma separated list of week days, e.g. `Thursday,Friday`.
+    days: Option<String>,
+
+    /// The earliest start time of a meeting as `HH:MM`.
+    start_after: Option<String>,
+
+    /// The latest start time of a meeting as `HH:MM`, this may be before `start_after` to search
+    /// past midnight.
+    start_before: Option<String>,
+
+    /// The country of the meeting, compared case insensitively.
+    country: Option<String>,
+
+    /// The region of the meeting, compared case insensitively.
+    region: Option<String>,
+
+    /// The maximum number of meetings to return, at most 1000.
+    limit: Option<usize>,
+
+    /// The number of meetings to skip.
+    offset: Option<usize>,
+}
+
+#[derive(Error, Debug)]
+#[error("Invalid value \"{value}\" for query parameter \"{param}\"")]
+struct InvalidQueryError {
+    param: &'static str,
+    value: String,
+}
+
+impl ResponseError for InvalidQueryError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn error_response(&self) -> HttpResponse<BoxBody> {
+        HttpResponse::build(self.status_code()).json(ApiError {
+            message: self.to_string(),
+        })
+    }
+}
+
+fn parse_list<T: FromStr>(
+    param: &'static str,
+    list: Option<String>,
+) -> Result<Vec<T>, InvalidQueryError> {
+    let Some(list) = list else {
+        return Ok(Vec::new());
+    };
+
+    list.split(',')
+        .map(|value| {
+            value.trim().parse().map_err(|_| InvalidQueryError {
+                param,
+                value: value.to_string(),
+            })
+        })
+        .collect()
 }
 
-impl Into<SearchOptions> for SearchQuery {
-    fn into(self) -> SearchOptions {
-        SearchOptions {
-            distance: match (self.longitude, self.latitude, self.distance) {
+fn parse_time(
+    param: &'static str,
+    time: Option<String>,
+) -> Result<Option<NaiveTime>, InvalidQueryError> {
+    time.map(|time| {
+        NaiveTime::parse_from_str(&time, "%H:%M")
+            .map_err(|_| InvalidQueryError { param, value: time })
+    })
+    .transpose()
+}
+
+fn parse_bounding_box(bbox: Option<String>) -> Result<Option<BoundingBox>, InvalidQueryError> {
+    let Some(bbox) = bbox else {
+        return Ok(None);
+    };
+
+    let invalid = || InvalidQueryError {
+        param: "bbox",
+        value: bbox.clone(),
+    };
+
+    let corners: Vec<f64> = parse_list("bbox", Some(bbox.clone()))?;
+
+    let [west, south, east, north] = corners[..] else {
+        return Err(invalid());
+    };
+
+    let valid_latitude = |lat: f64| (-90.0..=90.0).contains(&lat);
+    let valid_longitude = |long: f64| (-180.0..=180.0).contains(&long);
+
+    if !valid_latitude(south) || !valid_latitude(north) || south > north {
+        return Err(invalid());
+    }
+
+    if !valid_longitude(west) || !valid_longitude(east) {
+        return Err(invalid());
+    }
+
+    Ok(Some(BoundingBox {
+        south,
+        west,
+        north,
+        east,
+    }))
+}
+
+impl TryFrom<SearchQuery> for SearchOptions {
+    type Error = InvalidQueryError;
+
+    fn try_from(query: SearchQuery) -> Result<Self, Self::Error> {
+        Ok(SearchOptions {
+            distance: match (query.longitude, query.latitude, query.distance) {
                 (Some(longitude), Some(latitude), Some(distance)) => Some(DistanceSearch {
                     latitude,
                     longitude,
@@ -41,7 +170,24 @@ impl Into<SearchOptions> for SearchQuery {
                 }),
                 _ => None,
             },
-        }
+            bounds: parse_bounding_box(query.bbox)?,
+            text: query.q.filter(|q| !q.trim().is_empty()),
+            types: parse_list("types", query.types)?,
+            attendance: parse_list("attendance", query.attendance)?,
+            orgs: parse_list("orgs", query.orgs)?,
+            days: parse_list("days", query.days)?,
+            start_after: parse_time("start_after", query.start_after)?,
+            start_before: parse_time("start_before", query.start_before)?,
+            country: query.country,
+            region: query.region,
+            limit: Some(
+                query
+                    .limit
+                    .unwrap_or(DEFAULT_PAGE_SIZE)
+                    .min(MAX_PAGE_SIZE),
+            ),
+            offset: query.offset.unwrap_or_default(),
+        })
     }
 }
 
@@ -60,28 +206,56 @@ impl ResponseError for IndexError {
 #[utoipa::path(
     params(SearchQuery),
     responses(
-        (status = 200, description = "Retrieve a list of meetings", body = [SearchMeeting]))
+        (status = 200, description = "Retrieve a page of meetings", body = SearchResults),
+        (status = 400, description = "One of the query parameters is invalid"))
     )
 ]
 #[get("/meetings")]
 async fn index(
     meeting_index: web::Data<MeetingIndex>,
     query: web::Query<SearchQuery>,
-) -> Result<impl Responder, IndexError> {
-    let query = query.into_inner();
+) -> Result<impl Responder, actix_web::Error> {
+    let opts = query.into_inner().try_into()?;
 
-    let meetings = meeting_index.search(&query.into()).await?;
+    let meetings = meeting_index.search(&opts).await?;
     Ok(web::Json(meetings))
 }
 
+#[utoipa::path(
+    params(
+        ("id" = String, Path, description = "The id of the meeting")
+    ),
+    responses(
+        (status = 200, description = "Retrieve a single meeting", body = meeting::Meeting),
+        (status = 404, description = "There is no meeting with this id"))
+    )
+]
+#[get("/meetings/{id}")]
+async fn get_meeting(
+    meeting_index: web::Data<MeetingIndex>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, IndexError> {
+    let id = id.into_inner();
+
+    match meeting_index.get(&id).await? {
+        Some(meeting) => Ok(HttpResponse::Ok().json(meeting)),
+        None => Ok(HttpResponse::NotFound().json(ApiError {
+            message: format!("There is no meeting with id \"{id}\""),
+        })),
+    }
+}
+
 #[derive(OpenApi)]
 #[openapi(
-    paths(index),
+    paths(index, get_meeting),
     components(schemas(
+        SearchResults,
         SearchMeeting,
         meeting::Meeting,
         meeting::OnlineOptions,
+        meeting::AttendanceMode,
         meeting::MeetingTime,
+        meeting::MeetingType,
         meeting::WeekDay,
         meeting::Contact,
         meeting::Location,
@@ -111,6 +285,7 @@ pub async fn start_server(
             .wrap(cors)
             .app_data(web::Data::new(meeting_index.clone()))
             .service(index)
+            .service(get_meeting)
             .service(SwaggerUi::new("/{_:.*}").url("openapi.json", openapi.clone()))
     })
     .bind((address, port))?
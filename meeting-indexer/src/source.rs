@@ -2,10 +2,16 @@ mod na_holland;
 mod wp_sites;
 mod bmlt;
 
+use std::fmt::Debug;
+
+use chrono_tz::Tz;
+use futures_util::future::{join_all, BoxFuture};
+use serde::Serialize;
 use thiserror::Error;
-use tokio::{join, sync::mpsc::Sender};
+use tokio::sync::mpsc::Sender;
 
-use crate::meeting::Meeting;
+use crate::config::{Config, ConfigError, SourceConfig, SourceType};
+use crate::meeting::{Meeting, Organization};
 
 #[derive(Error, Debug)]
 pub enum MeetingFetchError {
@@ -25,12 +31,262 @@ pub struct FetchMeeting {
     pub position_query: Option<String>,
 }
 
-pub type FetchMeetingResult = Result<Vec<FetchMeeting>, MeetingFetchError>;
+/// Why a record of a source could not be converted to a meeting.
+#[derive(Error, Debug, Clone, Serialize)]
+#[error("{field}: {reason}")]
+pub struct ConversionError {
+    /// The field of the record as the source names it.
+    pub field: &'static str,
+    pub reason: String,
+}
+
+impl ConversionError {
+    pub fn new(field: &'static str, reason: impl Into<String>) -> Self {
+        Self {
+            field,
+            reason: reason.into(),
+        }
+    }
+
+    pub fn missing(field: &'static str) -> Self {
+        Self::new(field, "missing")
+    }
+
+    pub fn invalid(field: &'static str, expected: &str, value: impl Debug) -> Self {
+        Self::new(field, format!("expected {expected}, got {value:?}"))
+    }
+
+    pub fn for_record(self, record_id: impl ToString) -> ConversionFailure {
+        ConversionFailure {
+            record_id: record_id.to_string(),
+            error: self,
+        }
+    }
+}
+
+/// A record of a source that could not be converted to a meeting.
+#[derive(Error, Debug, Clone, Serialize)]
+#[error("record {record_id}: {error}")]
+pub struct ConversionFailure {
+    /// The id of the record in the source.
+    pub record_id: String,
+    #[serde(flatten)]
+    pub error: ConversionError,
+}
+
+/// The meetings of a source, along with the records that could not be converted.
+#[derive(Debug, Default)]
+pub struct FetchedMeetings {
+    pub meetings: Vec<FetchMeeting>,
+    pub conversion_failures: Vec<ConversionFailure>,
+}
+
+impl FromIterator<Result<FetchMeeting, ConversionFailure>> for FetchedMeetings {
+    fn from_iter<I: IntoIterator<Item = Result<FetchMeeting, ConversionFailure>>>(iter: I) -> Self {
+        let mut fetched = FetchedMeetings::default();
+
+        for result in iter {
+            match result {
+                Ok(meeting) => fetched.meetings.push(meeting),
+                Err(failure) => fetched.conversion_failures.push(failure),
+            }
+        }
+
+        fetched
+    }
+}
+
+pub type FetchMeetingResult = Result<FetchedMeetings, MeetingFetchError>;
+
+/// The meetings of a single source, or the reason they could not be fetched.
+#[derive(Debug)]
+pub struct SourceResult {
+    /// Identifies the source. Sources found through another source are prefixed with its name
+    /// and a `/`, like `bmlt/bmlt.sezf.org`.
+    pub source: String,
+    pub result: FetchMeetingResult,
+}
+
+/// A source of meetings that can be synced, enabled and disabled on its own.
+pub trait MeetingSource: Send + Sync {
+    /// Identifies the source, both in the index and on the command line.
+    fn name(&self) -> &str;
+
+    fn organization(&self) -> Organization;
+
+    /// Where the meetings are fetched from.
+    fn url(&self) -> &str;
+
+    /// Fetch the meetings of the source. Sources that find other sources, like the root servers
+    /// of BMLT, return one result per sub-source.
+    fn fetch(&self) -> BoxFuture<'_, Vec<SourceResult>>;
+}
+
+/// A source along with the settings from its configuration.
+pub struct RegisteredSource {
+    pub source: Box<dyn MeetingSource>,
+    /// Disabled sources are only synced when they are selected explicitly.
+    pub enabled: bool,
+    pub default_country: Option<String>,
+    pub default_timezone: Option<Tz>,
+}
+
+impl RegisteredSource {
+    /// Whether the source is selected, or enabled when nothing is selected.
+    fn is_fetched(&self, selected: &[String]) -> bool {
+        if selected.is_empty() {
+            self.enabled
+        } else {
+            selected.iter().any(|s| s == self.name())
+        }
+    }
+
+    fn from_config(config: &SourceConfig) -> Result<Self, String> {
+        let name = config.name.as_deref();
+
+        if let Some(name) = name {
+            // A `/` separates sources from the sub-sources they find
+            if name.is_empty() || name.contains('/') {
+                return Err(format!(
+                    "invalid name {name:?}, it can not be empty or contain a /"
+                ));
+            }
+        }
+
+        if let Some(country) = &config.country {
+            if country.len() != 2 || !country.chars().all(|c| c.is_ascii_alphabetic()) {
+                return Err(format!(
+                    "invalid country {country:?}, expected an ISO 3166-1 alpha-2 code like NL"
+                ));
+            }
+        }
+
+        if config.source_type != SourceType::Tsml && config.organization.is_some() {
+            return Err(format!(
+                "a {} source has no organization, it is always NarcoticsAnonymous",
+                config.source_type
+            ));
+        }
+
+        let source: Box<dyn MeetingSource> = match config.source_type {
+            SourceType::Tsml => {
+                let url = config.url.as_deref().ok_or("a tsml source needs a url")?;
+                let org = config
+                    .organization
+                    .as_deref()
+                    .ok_or("a tsml source needs an organization")?;
+                let org = org
+                    .parse()
+                    .map_err(|_| format!("unknown organization {org}"))?;
+
+                Box::new(wp_sites::TsmlSource::new(org, url, name))
+            }
+            SourceType::Bmlt => {
+                let url = config.url.as_deref().ok_or("a bmlt source needs a url")?;
+                Box::new(bmlt::BmltSource::new(url, name))
+            }
+            SourceType::NaHolland => Box::new(na_holland::NaHollandSource::new(
+                config.url.as_deref(),
+                name,
+            )),
+        };
+
+        Ok(Self {
+            source,
+            enabled: config.enabled,
+            default_country: config.country.as_deref().map(str::to_uppercase),
+            default_timezone: config.timezone,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        self.source.name()
+    }
+
+    async fn fetch(&self) -> Vec<SourceResult> {
+        let mut results = self.source.fetch().await;
+
+        for result in &mut results {
+            let Ok(fetched) = &mut result.result else {
+                continue;
+            };
+
+            for meeting in &mut fetched.meetings {
+                let meeting = &mut meeting.meeting;
+
+                if meeting.location.country.is_none() {
+                    meeting.location.country = self.default_country.clone();
+                }
+
+                meeting.timezone = meeting.timezone.or(self.default_timezone);
+            }
+        }
+
+        results
+    }
+}
+
+/// The sources that get synced.
+pub struct SourceRegistry {
+    sources: Vec<RegisteredSource>,
+}
+
+impl SourceRegistry {
+    pub fn from_config(config: &Config) -> Result<Self, ConfigError> {
+        let mut sources: Vec<RegisteredSource> = Vec::new();
+
+        for (index, source_config) in config.sources.iter().enumerate() {
+            let source = RegisteredSource::from_config(source_config)
+                .map_err(|reason| ConfigError::InvalidSource { index, reason })?;
+
+            if sources.iter().any(|other| other.name() == source.name()) {
+                return Err(ConfigError::InvalidSource {
+                    index,
+                    reason: format!(
+                        "there already is a source named {}, give one of them a `name`",
+                        source.name()
+                    ),
+                });
+            }
+
+            sources.push(source);
+        }
+
+        Ok(Self { sources })
+    }
+
+    pub fn sources(&self) -> impl Iterator<Item = &RegisteredSource> {
+        self.sources.iter()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.sources().any(|source| source.name() == name)
+    }
+
+    /// The names of the sources that exist but are not fetched, like disabled ones.
+    pub fn unfetched(&self, selected: &[String]) -> Vec<String> {
+        self.sources()
+            .filter(|source| !source.is_fetched(selected))
+            .map(|source| source.name().to_string())
+            .collect()
+    }
+
+    /// Fetch the meetings of the selected sources, or of every enabled source when `selected` is
+    /// empty.
+    pub async fn fetch_all_meetings(&self, selected: &[String], output: Sender<SourceResult>) {
+        let futures = self
+            .sources()
+            .filter(|source| source.is_fetched(selected))
+            .map(|source| {
+                let output = output.clone();
+
+                async move {
+                    for result in source.fetch().await {
+                        output.send(result).await.unwrap();
+                    }
+                }
+            });
 
-pub async fn fetch_all_meetings(output: Sender<FetchMeetingResult>) {
-    join!(
-        wp_sites::fetch_meetings(output.clone()),
-        na_holland::fetch_meetings(output.clone()),
-        bmlt::fetch_meetings(output.clone()),
-    );
+        join_all(futures).await;
+    }
 }
@@ -1,12 +1,14 @@
 use std::time::Duration;
 
 use chrono::{NaiveTime, Timelike, Utc};
-use futures_util::future::join_all;
+use futures_util::future::{join_all, BoxFuture};
 use serde::Deserialize;
-use tokio::sync::mpsc::Sender;
 
 use crate::meeting::*;
-use crate::source::{FetchMeeting, FetchMeetingResult, MeetingFetchError};
+use crate::source::{
+    ConversionError, FetchMeeting, FetchMeetingResult, MeetingFetchError, MeetingSource,
+    SourceResult,
+};
 
 async fn fetch_all_meetings(api_url: &str) -> FetchMeetingResult {
     let query = "switcher=GetSearchResults&get_used_formats&lang_enum=en&data_field_key=location_postal_code_1,duration_time,start_time,time_zone,weekday_tinyint,service_body_bigint,location_province,location_municipality,location_street,location_info,location_neighborhood,formats,comments,location_sub_province,worldid_mixed,root_server_uri,id_bigint,venue_type,meeting_name,location_text,virtual_meeting_link,phone_meeting_number,latitude,longitude,contact_name_1,contact_phone_1,contact_email_1,contact_name_2,contact_phone_2,contact_email_2&callback=callback";
@@ -21,39 +23,75 @@ async fn fetch_all_meetings(api_url: &str) -> FetchMeetingResult {
     Ok(data
         .meetings
         .into_iter()
-        .filter_map(|m| m.try_into().ok())
+        .map(|m| {
+            let record_id = m.id_bigint.clone();
+            m.try_into()
+                .map_err(|e: ConversionError| e.for_record(record_id))
+        })
         .collect())
 }
 
-async fn fetch_and_send(api_url: String, output: Sender<FetchMeetingResult>) {
-    let result = fetch_all_meetings(&api_url).await;
-    output.send(result).await.unwrap();
+/// The namespace of a root server, without the `/main_server` most of them have.
+fn root_server_namespace(url: &str) -> String {
+    url_namespace(url.trim_end_matches('/').trim_end_matches("/main_server"))
 }
 
-async fn fetch_from_all_servers(
-    output: Sender<FetchMeetingResult>,
-) -> Result<(), MeetingFetchError> {
-    let servers: Vec<BmltServer> =
-        reqwest::get("https://tomato.bmltenabled.org/main_server/api/v1/rootservers/")
-            .await?
-            .json()
-            .await?;
-
-    let futures = servers.iter().map(|server| {
-        fetch_and_send(
-            format!("{}client_interface/jsonp/", server.url),
-            output.clone(),
-        )
-    });
-
-    join_all(futures).await;
-
-    Ok(())
+/// Every BMLT root server listed by an aggregator like tomato, each root server is a sub-source.
+/// Its meetings get no country, that comes from the configuration of the source.
+pub struct BmltSource {
+    name: String,
+    root_servers_url: String,
 }
 
-pub async fn fetch_meetings(output: Sender<FetchMeetingResult>) {
-    if let Err(e) = fetch_from_all_servers(output.clone()).await {
-        output.send(Err(e)).await.unwrap();
+impl BmltSource {
+    /// Named `bmlt` unless a `name` is given.
+    pub fn new(root_servers_url: &str, name: Option<&str>) -> Self {
+        Self {
+            name: name.unwrap_or("bmlt").to_string(),
+            root_servers_url: root_servers_url.to_string(),
+        }
+    }
+
+    async fn fetch_server(&self, server_url: &str) -> SourceResult {
+        let api_url = format!("{server_url}client_interface/jsonp/");
+
+        SourceResult {
+            source: format!("{}/{}", self.name(), root_server_namespace(server_url)),
+            result: fetch_all_meetings(&api_url).await,
+        }
+    }
+
+    async fn fetch_all_servers(&self) -> Result<Vec<SourceResult>, MeetingFetchError> {
+        let servers: Vec<BmltServer> = reqwest::get(&self.root_servers_url).await?.json().await?;
+
+        let futures = servers.iter().map(|server| self.fetch_server(&server.url));
+
+        Ok(join_all(futures).await)
+    }
+}
+
+impl MeetingSource for BmltSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn organization(&self) -> Organization {
+        Organization::NarcoticsAnonymous
+    }
+
+    fn url(&self) -> &str {
+        &self.root_servers_url
+    }
+
+    fn fetch(&self) -> BoxFuture<'_, Vec<SourceResult>> {
+        Box::pin(async move {
+            self.fetch_all_servers().await.unwrap_or_else(|e| {
+                vec![SourceResult {
+                    source: self.name().to_string(),
+                    result: Err(e),
+                }]
+            })
+        })
     }
 }
 
@@ -125,15 +163,70 @@ struct ApiMeeting {
     pub virtual_meeting_link: String,
     #[serde(rename = "root_server_uri")]
     pub root_server_uri: String,
+    #[serde(rename = "time_zone", default)]
+    pub time_zone: String,
+}
+
+/// Map a BMLT format key to our meeting types, following the NAWS shared formats.
+fn meeting_types_from_format(code: &str) -> Vec<MeetingType> {
+    match code {
+        "O" => vec![MeetingType::Open],
+        "C" => vec![MeetingType::Closed],
+        "So" | "SPK" => vec![MeetingType::Speaker],
+        "SD" => vec![MeetingType::Speaker, MeetingType::Discussion],
+        "D" | "Rr" | "To" | "QA" => vec![MeetingType::Discussion],
+        "St" | "SG" => vec![MeetingType::StepStudy],
+        "Tr" | "TW" => vec![MeetingType::TraditionStudy],
+        "BT" | "IW" | "JT" | "LC" | "GP" | "IP" | "Lit" | "BK" => vec![MeetingType::Literature],
+        "ME" => vec![MeetingType::Meditation],
+        "B" => vec![MeetingType::Beginners],
+        "W" => vec![MeetingType::Women],
+        "M" => vec![MeetingType::Men],
+        "GL" | "LGBTQ" => vec![MeetingType::Lgbtq],
+        "YP" => vec![MeetingType::YoungPeople],
+        "WC" => vec![MeetingType::WheelchairAccess],
+        "SL" | "ASL" => vec![MeetingType::SignLanguage],
+        "Ag" => vec![MeetingType::Secular],
+        "NS" => vec![MeetingType::NonSmoking],
+        "EN" | "ENG" => vec![MeetingType::Language(String::from("en"))],
+        "ES" | "SPA" => vec![MeetingType::Language(String::from("es"))],
+        "FR" | "FRA" => vec![MeetingType::Language(String::from("fr"))],
+        "DE" | "GER" => vec![MeetingType::Language(String::from("de"))],
+        "PT" | "POR" => vec![MeetingType::Language(String::from("pt"))],
+        "FIN" => vec![MeetingType::Language(String::from("fi"))],
+        "PER" => vec![MeetingType::Language(String::from("fa"))],
+        _ => vec![],
+    }
+}
+
+fn parse_time(field: &'static str, value: &str) -> Result<NaiveTime, ConversionError> {
+    NaiveTime::parse_from_str(value, "%H:%M:%S")
+        .map_err(|_| ConversionError::invalid(field, "a time like 19:30:00", value))
+}
+
+fn parse_coordinate(field: &'static str, value: &str) -> Result<f64, ConversionError> {
+    value
+        .parse()
+        .map_err(|_| ConversionError::invalid(field, "a number", value))
 }
 
 impl TryInto<FetchMeeting> for ApiMeeting {
-    type Error = ();
+    type Error = ConversionError;
 
     fn try_into(self) -> Result<FetchMeeting, Self::Error> {
-        let start_time = NaiveTime::parse_from_str(&self.start_time, "%H:%M:%S").map_err(|_| ())?;
-        let duration =
-            NaiveTime::parse_from_str(&self.duration_time, "%H:%M:%S").map_err(|_| ())?;
+        let start_time = parse_time("start_time", &self.start_time)?;
+        let duration = parse_time("duration_time", &self.duration_time)?;
+
+        let day = match self.weekday_tinyint.parse::<u8>() {
+            Ok(day @ 1..=7) => WeekDay::from_day_index(day - 1),
+            _ => {
+                return Err(ConversionError::invalid(
+                    "weekday_tinyint",
+                    "a day from 1 to 7",
+                    &self.weekday_tinyint,
+                ))
+            }
+        };
 
         let email = if !self.contact_email_1.is_empty() {
             Some(self.contact_email_1)
@@ -151,11 +244,37 @@ impl TryInto<FetchMeeting> for ApiMeeting {
             None
         };
 
-        let is_online = self.formats.contains("VM");
+        let formats: Vec<&str> = self.formats.split(',').map(|code| code.trim()).collect();
+
+        let attendance = match (
+            formats.contains(&"HY"),
+            formats.contains(&"VM"),
+            formats.contains(&"TC"),
+        ) {
+            (true, _, _) => AttendanceMode::Hybrid,
+            (false, true, _) => AttendanceMode::Online,
+            (false, false, true) => AttendanceMode::TemporarilyInactive,
+            (false, false, false) => AttendanceMode::InPerson,
+        };
+
+        let mut types: Vec<MeetingType> = formats
+            .iter()
+            .flat_map(|code| meeting_types_from_format(code))
+            .collect();
+        types.sort();
+        types.dedup();
+
+        let source = self
+            .root_server_uri
+            .trim_end_matches('/')
+            .trim_end_matches("/main_server")
+            .to_string();
+        let namespace = root_server_namespace(&self.root_server_uri);
 
         Ok(FetchMeeting {
             position_query: None,
             meeting: Meeting {
+                id: Meeting::make_id(&namespace, &self.id_bigint),
                 name: self.meeting_name,
                 org: Organization::NarcoticsAnonymous,
                 notes: if self.comments.is_empty() {
@@ -163,13 +282,13 @@ impl TryInto<FetchMeeting> for ApiMeeting {
                 } else {
                     Some(self.comments)
                 },
-                source: self.root_server_uri.trim_end_matches("/").trim_end_matches("/main_server").to_string(),
+                source,
                 updated_at: Utc::now(),
                 contact: Contact { email, phone },
                 location: Location {
                     position: Some(Position::new(
-                        self.latitude.parse().map_err(|_| ())?,
-                        self.longitude.parse().map_err(|_| ())?,
+                        parse_coordinate("latitude", &self.latitude)?,
+                        parse_coordinate("longitude", &self.longitude)?,
                     )),
                     name: if self.location_text.is_empty() {
                         None
@@ -181,7 +300,7 @@ impl TryInto<FetchMeeting> for ApiMeeting {
                     } else {
                         Some(self.location_info)
                     },
-                    country: Some(String::from("United States")),
+                    country: None,
                     region: Some(self.location_province),
                     address: if !self.location_street.is_empty() {
                         Some(self.location_street)
@@ -189,8 +308,9 @@ impl TryInto<FetchMeeting> for ApiMeeting {
                         None
                     },
                 },
+                attendance,
                 online_options: OnlineOptions {
-                    url: if self.virtual_meeting_link.is_empty() || !is_online {
+                    url: if self.virtual_meeting_link.is_empty() || !attendance.is_online() {
                         None
                     } else {
                         Some(self.virtual_meeting_link)
@@ -200,15 +320,14 @@ impl TryInto<FetchMeeting> for ApiMeeting {
                     } else {
                         Some(self.phone_meeting_number)
                     },
-                    is_online,
                 },
+                types,
                 time: MeetingTime::Recurring {
-                    day: WeekDay::from_day_index(
-                        self.weekday_tinyint.parse::<u8>().map_err(|_| ())? - 1,
-                    ),
+                    day,
                     minute: start_time.minute() as i32,
                     hour: start_time.hour() as i32,
                 },
+                timezone: parse_timezone(&self.time_zone),
                 duration: Some(Duration::from_secs(
                     (duration.minute() * 60 + (duration.hour() * 60 * 60) + duration.second())
                         as u64,
@@ -1,10 +1,15 @@
 use crate::meeting::*;
-use crate::source::{FetchMeeting, FetchMeetingResult};
+use crate::source::{
+    ConversionError, FetchMeeting, FetchMeetingResult, MeetingSource, SourceResult,
+};
 use chrono::{NaiveTime, Timelike, Utc};
+use chrono_tz::Europe::Amsterdam;
+use futures_util::future::BoxFuture;
 use lazy_static::lazy_static;
 use regex::Regex;
 use serde::Deserialize;
-use tokio::sync::mpsc::Sender;
+
+const API_ORIGIN: &str = "https://www.na-holland.nl";
 
 async fn fetch_all_meetings(api_url: &str) -> FetchMeetingResult {
     let data: ApiData = reqwest::get(api_url).await?.json().await?;
@@ -12,13 +17,52 @@ async fn fetch_all_meetings(api_url: &str) -> FetchMeetingResult {
     Ok(data
         .meetings
         .into_iter()
-        .filter_map(|m| m.try_into().ok())
+        .map(|m| {
+            let record_id = m.id;
+            m.try_into()
+                .map_err(|e: ConversionError| e.for_record(record_id))
+        })
         .collect())
 }
 
-pub async fn fetch_meetings(output: Sender<FetchMeetingResult>) {
-    let result = fetch_all_meetings("https://www.na-holland.nl/api/v1/meetings").await;
-    output.send(result).await.unwrap();
+pub struct NaHollandSource {
+    name: String,
+    meetings_url: String,
+}
+
+impl NaHollandSource {
+    /// The API of na-holland.nl and its name, unless they are given.
+    pub fn new(meetings_url: Option<&str>, name: Option<&str>) -> Self {
+        Self {
+            name: name.unwrap_or("na-holland.nl").to_string(),
+            meetings_url: meetings_url
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("{API_ORIGIN}/api/v1/meetings")),
+        }
+    }
+}
+
+impl MeetingSource for NaHollandSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn organization(&self) -> Organization {
+        Organization::NarcoticsAnonymous
+    }
+
+    fn url(&self) -> &str {
+        &self.meetings_url
+    }
+
+    fn fetch(&self) -> BoxFuture<'_, Vec<SourceResult>> {
+        Box::pin(async move {
+            vec![SourceResult {
+                source: self.name().to_string(),
+                result: fetch_all_meetings(&self.meetings_url).await,
+            }]
+        })
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Deserialize)]
@@ -44,12 +88,8 @@ struct ApiRecord {
     pub details: String,
 }
 
-enum ConvertError {
-    TimeParseError,
-}
-
 impl TryInto<FetchMeeting> for ApiRecord {
-    type Error = ConvertError;
+    type Error = ConversionError;
 
     fn try_into(self) -> Result<FetchMeeting, Self::Error> {
         lazy_static! {
@@ -57,10 +97,18 @@ impl TryInto<FetchMeeting> for ApiRecord {
         }
 
         let start_time = NaiveTime::parse_from_str(&self.start, "%H:%M")
-            .map_err(|_| ConvertError::TimeParseError)?;
+            .map_err(|_| ConversionError::invalid("start", "a time like 19:30", &self.start))?;
 
         let end_time = NaiveTime::parse_from_str(&self.finish, "%H:%M")
-            .map_err(|_| ConvertError::TimeParseError)?;
+            .map_err(|_| ConversionError::invalid("finish", "a time like 19:30", &self.finish))?;
+
+        if !(1..=7).contains(&self.weekday) {
+            return Err(ConversionError::invalid(
+                "weekday",
+                "a day from 1 to 7",
+                self.weekday,
+            ));
+        }
 
         let query = if let Some(street_match) = STREET_REGEX.find(&self.address) {
             let mut value = street_match.as_str().to_string();
@@ -73,25 +121,31 @@ impl TryInto<FetchMeeting> for ApiRecord {
         Ok(FetchMeeting {
             position_query: query,
             meeting: Meeting {
+                id: Meeting::make_id(&url_namespace(API_ORIGIN), self.id),
                 name: format!("NA Holland | {} {}", self.city_name, self.address),
                 org: Organization::NarcoticsAnonymous,
                 notes: Some(self.details),
-                source: format!("https://www.na-holland.nl/#/meetings/{}", self.id),
+                source: format!("{API_ORIGIN}/#/meetings/{}", self.id),
                 updated_at: Utc::now(),
                 contact: Contact {
                     email: None,
                     phone: None,
                 },
+                attendance: if self.province_name == "ONLINE" {
+                    AttendanceMode::Online
+                } else {
+                    AttendanceMode::InPerson
+                },
                 online_options: OnlineOptions {
                     url: None,
                     notes: None,
-                    is_online: self.province_name == "ONLINE",
                 },
+                types: Vec::new(),
                 location: Location {
                     position: None,
                     name: None,
                     notes: None,
-                    country: Some(String::from("Nederland")),
+                    country: Some(String::from("NL")),
                     region: Some(self.province_name),
                     address: Some(self.address),
                 },
@@ -100,6 +154,7 @@ impl TryInto<FetchMeeting> for ApiRecord {
                     hour: start_time.hour() as i32,
                     minute: start_time.minute() as i32,
                 },
+                timezone: Some(Amsterdam),
                 duration: (end_time - start_time).to_std().ok(),
             },
         })
@@ -1,23 +1,28 @@
 use std::collections::HashMap;
+use std::str::FromStr;
 use std::time::Duration;
 
 use chrono::{NaiveTime, Timelike, Utc};
+use chrono_tz::Tz;
+use futures_util::future::BoxFuture;
 use select::document::Document;
 use select::predicate::Attr;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tokio::join;
-use tokio::sync::mpsc::Sender;
 
 use crate::meeting::*;
-use crate::source::{FetchMeeting, MeetingFetchError};
+use crate::source::{
+    ConversionError, FetchMeeting, MeetingFetchError, MeetingSource, SourceResult,
+};
 
 use super::FetchMeetingResult;
 
 struct Metadata {
     nonce: String,
-    _meeting_type_map: HashMap<String, String>,
+    meeting_type_map: HashMap<String, String>,
     endpoint: String,
+    /// The time zone set for the whole site, if TSML gives it.
+    timezone: Option<Tz>,
 }
 
 async fn fetch_metadata(meeting_url: &str) -> Result<Metadata, MeetingFetchError> {
@@ -85,10 +90,22 @@ async fn fetch_metadata(meeting_url: &str) -> Result<Metadata, MeetingFetchError
             .unwrap()
             .to_string(),
 
-        _meeting_type_map: meeting_type_map,
+        timezone: json
+            .get("timezone")
+            .and_then(Value::as_str)
+            .and_then(parse_timezone),
+
+        meeting_type_map,
     })
 }
 
+/// The namespace of the whole site, so it does not change with the path of the meetings page.
+fn site_namespace(meetings_url: &str) -> String {
+    reqwest::Url::parse(meetings_url)
+        .map(|url| url_namespace(&url.origin().ascii_serialization()))
+        .unwrap_or_else(|_| url_namespace(meetings_url))
+}
+
 async fn fetch_all_meetings(meetings_url: &str, org: Organization) -> FetchMeetingResult {
     let metadata = fetch_metadata(meetings_url).await?;
 
@@ -110,31 +127,159 @@ async fn fetch_all_meetings(meetings_url: &str, org: Organization) -> FetchMeeti
         .json()
         .await?;
 
+    let namespace = site_namespace(meetings_url);
+
     Ok(res
         .into_iter()
-        .filter_map(|m| {
-            m.try_into().ok().map(|mut m: FetchMeeting| {
-                m.meeting.org = org.clone();
-                m
-            })
+        .map(|m| {
+            let record_id = m.id;
+            let id = Meeting::make_id(&namespace, m.id);
+            let types = meeting_types(&m.types, &metadata.meeting_type_map);
+
+            m.try_into()
+                .map(|mut m: FetchMeeting| {
+                    m.meeting.id = id;
+                    m.meeting.org = org.clone();
+                    m.meeting.types = types;
+                    // The time zone of the meeting comes first, then that of the site, and the
+                    // one configured for the source last
+                    m.meeting.timezone = m.meeting.timezone.or(metadata.timezone);
+                    m
+                })
+                .map_err(|e: ConversionError| e.for_record(record_id))
         })
         .collect())
 }
 
-async fn fetch_and_send(org: Organization, meetings_url: &str, output: Sender<FetchMeetingResult>) {
-    let result = fetch_all_meetings(meetings_url, org).await;
-    output.send(result).await.unwrap();
+/// Map TSML type codes to our meeting types. Codes that are not part of the standard TSML set
+/// are looked up by their name in the type map of the site.
+fn meeting_types(codes: &[String], type_map: &HashMap<String, String>) -> Vec<MeetingType> {
+    let mut types: Vec<MeetingType> = codes
+        .iter()
+        .filter_map(|code| {
+            meeting_type_from_code(code).or_else(|| {
+                type_map
+                    .get(code)
+                    .and_then(|name| meeting_type_from_name(name))
+            })
+        })
+        .collect();
+
+    types.sort();
+    types.dedup();
+    types
+}
+
+fn meeting_type_from_code(code: &str) -> Option<MeetingType> {
+    let meeting_type = match code {
+        "O" => MeetingType::Open,
+        "C" => MeetingType::Closed,
+        "SP" => MeetingType::Speaker,
+        "D" | "XT" => MeetingType::Discussion,
+        "ST" | "12x12" => MeetingType::StepStudy,
+        "TR" => MeetingType::TraditionStudy,
+        "B" | "LIT" | "ABSI" | "DR" | "LS" | "GR" => MeetingType::Literature,
+        "MED" | "11" => MeetingType::Meditation,
+        "BE" => MeetingType::Beginners,
+        "W" => MeetingType::Women,
+        "M" => MeetingType::Men,
+        "LGBTQ" | "G" | "L" | "T" => MeetingType::Lgbtq,
+        "Y" => MeetingType::YoungPeople,
+        "X" => MeetingType::WheelchairAccess,
+        "ASL" => MeetingType::SignLanguage,
+        "A" => MeetingType::Secular,
+        "NS" => MeetingType::NonSmoking,
+        "EN" => MeetingType::Language(String::from("en")),
+        "S" => MeetingType::Language(String::from("es")),
+        "FR" => MeetingType::Language(String::from("fr")),
+        "ITA" => MeetingType::Language(String::from("it")),
+        "JA" => MeetingType::Language(String::from("ja")),
+        "KOR" => MeetingType::Language(String::from("ko")),
+        "POL" => MeetingType::Language(String::from("pl")),
+        "POR" => MeetingType::Language(String::from("pt")),
+        "RUS" => MeetingType::Language(String::from("ru")),
+        "HE" => MeetingType::Language(String::from("he")),
+        "PUN" => MeetingType::Language(String::from("pa")),
+        _ => return None,
+    };
+
+    Some(meeting_type)
+}
+
+fn meeting_type_from_name(name: &str) -> Option<MeetingType> {
+    let language = match name.trim().to_lowercase().as_str() {
+        "english" => "en",
+        "dutch" | "nederlands" | "nederlandstalig" => "nl",
+        "german" | "deutsch" => "de",
+        "french" | "français" => "fr",
+        "spanish" | "español" => "es",
+        "italian" => "it",
+        "portuguese" => "pt",
+        "polish" => "pl",
+        "russian" => "ru",
+        "swedish" => "sv",
+        "danish" => "da",
+        "norwegian" => "no",
+        "finnish" => "fi",
+        "greek" => "el",
+        "turkish" => "tr",
+        "arabic" => "ar",
+        "farsi" | "persian" => "fa",
+        "hebrew" => "he",
+        "hungarian" => "hu",
+        "czech" => "cs",
+        "lithuanian" => "lt",
+        "japanese" => "ja",
+        "korean" => "ko",
+        _ => return None,
+    };
+
+    Some(MeetingType::Language(String::from(language)))
+}
+
+/// A WordPress site with the 12 Step Meeting List plugin.
+pub struct TsmlSource {
+    name: String,
+    org: Organization,
+    meetings_url: String,
 }
 
-pub async fn fetch_meetings(output: Sender<FetchMeetingResult>) {
-    join!(
-        fetch_and_send(Organization::AnonymousAlcoholics, "https://alcoholics-anonymous.eu/meetings/?tsml-day=6&tsml-view=map", output.clone()),
-        fetch_and_send(Organization::DebtorsAnonymous, "https://debtorsanonymous.org/meetings/?tsml-day=any", output.clone()),
-        fetch_and_send(Organization::CrystalMethAnonymous, "https://www.crystalmeth.org/meetings/?tsml-day=6", output.clone()),
-        fetch_and_send(Organization::CodependentsAnonymous, "https://www.codependents-anonymous.nl/v2/meetings/?tsml-day=any&tsml-attendance_option=active", output.clone()),
-        fetch_and_send(Organization::CodependentsAnonymous, "https://codacanada.ca/?tsml-day=any&post_type=tsml_meeting", output.clone()),
-        fetch_and_send(Organization::CodependentsAnonymous, "https://codauk.org/meetings/?tsml-day=any", output.clone()),
-    );
+impl TsmlSource {
+    /// Named after the site unless a `name` is given.
+    pub fn new(org: Organization, meetings_url: &str, name: Option<&str>) -> Self {
+        Self {
+            name: name
+                .map(str::to_string)
+                .unwrap_or_else(|| site_namespace(meetings_url)),
+            org,
+            meetings_url: meetings_url.to_string(),
+        }
+    }
+}
+
+impl MeetingSource for TsmlSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn organization(&self) -> Organization {
+        self.org.clone()
+    }
+
+    fn url(&self) -> &str {
+        &self.meetings_url
+    }
+
+    fn fetch(&self) -> BoxFuture<'_, Vec<SourceResult>> {
+        Box::pin(async move {
+            let result = fetch_all_meetings(&self.meetings_url, self.org.clone()).await;
+
+            vec![SourceResult {
+                source: self.name.clone(),
+                result,
+            }]
+        })
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -210,61 +355,69 @@ struct AAMeeting {
     pub mailing_address: Option<String>,
     #[serde(rename = "group_notes")]
     pub group_notes: Option<String>,
+    pub timezone: Option<String>,
 }
 
-enum ConvertError {
-    MissingField,
-    ParseError,
+/// TSML sends numbers either as JSON numbers or as strings.
+fn parse_number<T: FromStr>(field: &'static str, value: &Value) -> Result<T, ConversionError> {
+    let parsed = match value {
+        Value::Number(number) => number.to_string().parse().ok(),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    };
+
+    parsed.ok_or_else(|| ConversionError::new(field, format!("expected a number, got {value}")))
+}
+
+fn parse_time(field: &'static str, value: &str) -> Result<NaiveTime, ConversionError> {
+    NaiveTime::parse_from_str(value, "%H:%M")
+        .map_err(|_| ConversionError::invalid(field, "a time like 19:30", value))
 }
 
 impl TryInto<FetchMeeting> for AAMeeting {
-    type Error = ConvertError;
+    type Error = ConversionError;
 
     fn try_into(self) -> Result<FetchMeeting, Self::Error> {
-        let time = self.time.ok_or(ConvertError::MissingField)?;
-        let end_time = self.end_time;
-
-        let day_value = self.day.ok_or(ConvertError::MissingField)?;
-        let mut day: u8 = day_value
-            .as_u64()
-            .or_else(|| day_value.as_str().map(|s| s.parse().unwrap()))
-            .ok_or(ConvertError::ParseError)? as u8;
-
-        let convert_f64 = |val: Value| -> Result<f64, ConvertError> {
-            Ok(val
-                .as_f64()
-                .or_else(|| val.as_str().map(|s| s.parse().unwrap()))
-                .ok_or(ConvertError::ParseError)?)
-        };
-
-        let latitude = convert_f64(self.latitude)?;
-        let longitude = convert_f64(self.longitude)?;
-
-        // We specify that monday = 0 and sunday = 6.
-        if day == 0 {
-            day = 6;
-        } else {
-            day -= 1;
+        let time = self.time.ok_or(ConversionError::missing("time"))?;
+        let time = parse_time("time", &time)?;
+
+        let end_time = self
+            .end_time
+            .map(|end_time| parse_time("end_time", &end_time))
+            .transpose()?;
+
+        let day_value = self.day.ok_or(ConversionError::missing("day"))?;
+        let day: u8 = parse_number("day", &day_value)?;
+
+        if day > 6 {
+            return Err(ConversionError::invalid("day", "a day from 0 to 6", day));
         }
 
-        // TODO: remove all unwraps
+        let latitude = parse_number("latitude", &self.latitude)?;
+        let longitude = parse_number("longitude", &self.longitude)?;
 
-        let time =
-            NaiveTime::parse_from_str(&time, "%H:%M").map_err(|_| ConvertError::ParseError)?;
+        // TSML starts the week at sunday = 0, we specify that monday = 0 and sunday = 6.
+        let day = (day + 6) % 7;
 
         Ok(FetchMeeting {
             meeting: Meeting {
+                attendance: match self.attendance_option.as_deref() {
+                    Some("in_person") => AttendanceMode::InPerson,
+                    Some("online") => AttendanceMode::Online,
+                    Some("hybrid") => AttendanceMode::Hybrid,
+                    Some("inactive") => AttendanceMode::TemporarilyInactive,
+                    // Older TSML versions only have the online region.
+                    _ if self.region.as_deref() == Some("--Online--") => AttendanceMode::Online,
+                    _ => AttendanceMode::InPerson,
+                },
                 online_options: OnlineOptions {
-                    is_online: self
-                        .region
-                        .as_ref()
-                        .map(|region| region == "--Online--")
-                        .unwrap_or(false),
                     url: self.conference_url,
                     notes: self.conference_url_notes,
                 },
+                id: String::new(),
                 name: self.name,
                 source: self.url,
+                types: Vec::new(),
                 org: Organization::AnonymousAlcoholics,
                 contact: Contact {
                     email: self.email,
@@ -277,8 +430,12 @@ impl TryInto<FetchMeeting> for AAMeeting {
                     }),
                     name: self.location,
                     notes: self.location_notes,
-                    country: self.region,
-                    region: self.sub_region,
+                    // TSML regions are names chosen by the site, like a city, so the country
+                    // comes from the source or the boundaries instead.
+                    country: None,
+                    region: self
+                        .sub_region
+                        .or(self.region.filter(|region| region != "--Online--")),
                     address: self.formatted_address,
                 },
                 time: MeetingTime::Recurring {
@@ -287,14 +444,78 @@ impl TryInto<FetchMeeting> for AAMeeting {
                     minute: time.minute() as i32,
                 },
                 notes: self.notes,
-                duration: end_time.map(|end_time| {
-                    (NaiveTime::parse_from_str(&end_time, "%H:%M").unwrap() - time)
-                        .to_std()
-                        .unwrap_or(Duration::from_secs(1))
-                }),
+                timezone: self.timezone.as_deref().and_then(parse_timezone),
+                duration: end_time
+                    .map(|end_time| (end_time - time).to_std().unwrap_or(Duration::from_secs(1))),
                 updated_at: Utc::now(),
             },
             position_query: None,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meeting(timezone: Option<&str>) -> AAMeeting {
+        AAMeeting {
+            name: String::from("Thursday Night"),
+            url: String::from("https://example.org/meetings/thursday-night/"),
+            day: Some(Value::from(4)),
+            time: Some(String::from("19:30")),
+            latitude: Value::from("43.65"),
+            longitude: Value::from(-79.38),
+            timezone: timezone.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn keeps_the_time_zone_of_a_meeting() {
+        let fetched: FetchMeeting = meeting(Some("America/Toronto")).try_into().unwrap();
+
+        assert_eq!(fetched.meeting.timezone, Some(chrono_tz::America::Toronto));
+    }
+
+    #[test]
+    fn ignores_an_unknown_time_zone() {
+        for timezone in [None, Some(""), Some("Toronto")] {
+            let fetched: FetchMeeting = meeting(timezone).try_into().unwrap();
+
+            assert_eq!(fetched.meeting.timezone, None);
+        }
+    }
+
+    #[test]
+    fn uses_the_region_as_a_region_and_not_a_country() {
+        let fetched: FetchMeeting = AAMeeting {
+            region: Some(String::from("Toronto")),
+            ..meeting(None)
+        }
+        .try_into()
+        .unwrap();
+
+        assert_eq!(fetched.meeting.location.country, None);
+        assert_eq!(fetched.meeting.location.region.as_deref(), Some("Toronto"));
+
+        let fetched: FetchMeeting = AAMeeting {
+            region: Some(String::from("Toronto")),
+            sub_region: Some(String::from("Downtown")),
+            ..meeting(None)
+        }
+        .try_into()
+        .unwrap();
+
+        assert_eq!(fetched.meeting.location.region.as_deref(), Some("Downtown"));
+
+        let fetched: FetchMeeting = AAMeeting {
+            region: Some(String::from("--Online--")),
+            ..meeting(None)
+        }
+        .try_into()
+        .unwrap();
+
+        assert_eq!(fetched.meeting.location.region, None);
+    }
+}
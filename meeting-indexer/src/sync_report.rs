@@ -0,0 +1,258 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::meeting::{Location, Meeting, MeetingTime};
+use crate::source::ConversionFailure;
+
+/// How many failed records are listed per field.
+const SAMPLE_SIZE: usize = 3;
+
+/// A change to one of the fields of a meeting that matter to someone going there.
+#[derive(Serialize, Debug)]
+#[serde(tag = "field", rename_all = "snake_case")]
+pub enum MeetingChange {
+    Time {
+        before: MeetingTime,
+        after: MeetingTime,
+    },
+    Location {
+        before: Box<Location>,
+        after: Box<Location>,
+    },
+    OnlineUrl {
+        before: Option<String>,
+        after: Option<String>,
+    },
+}
+
+impl MeetingChange {
+    /// The changes between the previous and the new version of a meeting.
+    pub fn between(previous: &Meeting, meeting: &Meeting) -> Vec<MeetingChange> {
+        let mut changes = Vec::new();
+
+        if previous.time != meeting.time {
+            changes.push(MeetingChange::Time {
+                before: previous.time.clone(),
+                after: meeting.time.clone(),
+            });
+        }
+
+        if previous.location != meeting.location {
+            changes.push(MeetingChange::Location {
+                before: Box::new(previous.location.clone()),
+                after: Box::new(meeting.location.clone()),
+            });
+        }
+
+        if previous.online_options.url != meeting.online_options.url {
+            changes.push(MeetingChange::OnlineUrl {
+                before: previous.online_options.url.clone(),
+                after: meeting.online_options.url.clone(),
+            });
+        }
+
+        changes
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ModifiedMeeting {
+    pub id: String,
+    pub changes: Vec<MeetingChange>,
+}
+
+/// The records of a source that could not be converted because of the same field.
+#[derive(Serialize, Debug)]
+pub struct FieldFailures {
+    pub field: &'static str,
+    pub count: usize,
+    pub samples: Vec<ConversionFailure>,
+}
+
+impl FieldFailures {
+    pub fn group(failures: &[ConversionFailure]) -> Vec<FieldFailures> {
+        let mut fields: BTreeMap<&'static str, FieldFailures> = BTreeMap::new();
+
+        for failure in failures {
+            let field = fields
+                .entry(failure.error.field)
+                .or_insert_with(|| FieldFailures {
+                    field: failure.error.field,
+                    count: 0,
+                    samples: Vec::new(),
+                });
+
+            field.count += 1;
+
+            if field.samples.len() < SAMPLE_SIZE {
+                field.samples.push(failure.clone());
+            }
+        }
+
+        fields.into_values().collect()
+    }
+}
+
+impl fmt::Display for FieldFailures {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} failed, like ", self.field, self.count)?;
+
+        let samples: Vec<String> = self
+            .samples
+            .iter()
+            .map(|sample| format!("{} ({})", sample.record_id, sample.error.reason))
+            .collect();
+
+        write!(f, "{}", samples.join(", "))
+    }
+}
+
+/// A geocoded position that was left out because of the geocode rules.
+#[derive(Serialize, Debug)]
+pub struct RejectedPosition {
+    pub id: String,
+    pub query: String,
+    pub reason: String,
+}
+
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceStatus {
+    #[default]
+    Imported,
+    /// The source could not be fetched, its previous meetings were kept.
+    Failed,
+    /// The meetings of the source looked wrong, its previous meetings were kept.
+    HeldBack,
+    /// The source no longer exists, its meetings were removed.
+    Missing,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct SourceReport {
+    pub status: SourceStatus,
+    /// Why the source failed or was held back.
+    pub message: Option<String>,
+    /// The number of meetings the source returned.
+    pub meetings: usize,
+    /// The number of records of the source that could not be converted to meetings.
+    pub conversion_failures: usize,
+    pub failed_fields: Vec<FieldFailures>,
+    /// Meetings whose geocoded position was not used.
+    pub rejected_positions: Vec<RejectedPosition>,
+    pub added: Vec<String>,
+    pub modified: Vec<ModifiedMeeting>,
+    pub removed: Vec<String>,
+}
+
+impl SourceReport {
+    pub fn is_unchanged(&self) -> bool {
+        self.added.is_empty() && self.modified.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// What a sync changed in the index, per source.
+#[derive(Serialize, Debug)]
+pub struct SyncReport {
+    pub started_at: DateTime<Utc>,
+    pub committed: bool,
+    pub sources: BTreeMap<String, SourceReport>,
+}
+
+impl SyncReport {
+    pub fn new(started_at: DateTime<Utc>) -> Self {
+        Self {
+            started_at,
+            committed: false,
+            sources: BTreeMap::new(),
+        }
+    }
+
+    pub fn source(&mut self, source: &str) -> &mut SourceReport {
+        self.sources.entry(source.to_string()).or_default()
+    }
+
+    /// The sources with the given status.
+    pub fn sources_with_status(&self, status: SourceStatus) -> Vec<&str> {
+        self.sources
+            .iter()
+            .filter(|(_, report)| report.status == status)
+            .map(|(source, _)| source.as_str())
+            .collect()
+    }
+}
+
+impl fmt::Display for SyncReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (mut added, mut modified, mut removed) = (0, 0, 0);
+
+        writeln!(f, "Changes per source:")?;
+
+        for (source, report) in &self.sources {
+            write!(f, "  {source}: ")?;
+
+            if matches!(
+                report.status,
+                SourceStatus::Imported | SourceStatus::HeldBack
+            ) {
+                write!(f, "{} meetings, ", report.meetings)?;
+            }
+
+            match report.status {
+                SourceStatus::Imported if report.is_unchanged() => write!(f, "unchanged")?,
+                SourceStatus::Imported => write!(
+                    f,
+                    "{} added, {} modified, {} removed",
+                    report.added.len(),
+                    report.modified.len(),
+                    report.removed.len()
+                )?,
+                SourceStatus::Failed => write!(f, "failed, kept the previous meetings")?,
+                SourceStatus::HeldBack => write!(f, "held back, kept the previous meetings")?,
+                SourceStatus::Missing => {
+                    write!(f, "no longer exists, {} removed", report.removed.len())?
+                }
+            }
+
+            if report.conversion_failures > 0 {
+                write!(f, ", {} conversion failures", report.conversion_failures)?;
+            }
+
+            if !report.rejected_positions.is_empty() {
+                write!(
+                    f,
+                    ", {} positions rejected",
+                    report.rejected_positions.len()
+                )?;
+            }
+
+            if let Some(message) = &report.message {
+                write!(f, " ({message})")?;
+            }
+
+            writeln!(f)?;
+
+            for failed_field in &report.failed_fields {
+                writeln!(f, "    {failed_field}")?;
+            }
+
+            added += report.added.len();
+            modified += report.modified.len();
+            removed += report.removed.len();
+        }
+
+        write!(
+            f,
+            "Total: {added} added, {modified} modified, {removed} removed"
+        )?;
+
+        if !self.committed {
+            write!(f, " (not committed)")?;
+        }
+
+        Ok(())
+    }
+}